@@ -0,0 +1,700 @@
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+use crate::error::ApiError;
+use crate::handler::twitter::redeem_keys::SigningKeySource;
+use crate::handler::twitter::request_target::RequestTargetPolicy;
+use crate::logging::LogFormat;
+use crate::utils::http_transcript::TranscriptLimits;
+use crate::utils::redeem_store::ReplayPolicy;
+
+/// 指定 TOML 配置文件, 与 `--config` 相同
+pub const CONFIG_PATH_ENV: &str = "TEE_VERIFY_CONFIG";
+
+const LISTEN_ENV: &str = "LISTEN_ADDR";
+const LEGACY_ENVELOPE_ENV: &str = "LEGACY_ENVELOPE";
+const MAX_BODY_BYTES_ENV: &str = "MAX_BODY_BYTES";
+const ADMIN_TOKEN_ENV: &str = "ADMIN_TOKEN";
+const SIGNING_KEY_ENV: &str = "REDEEM_SIGNING_KEY";
+const SIGNING_KEY_PATH_ENV: &str = "REDEEM_SIGNING_KEY_PATH";
+const SIGNING_KEY_SEALED_ENV: &str = "REDEEM_SIGNING_KEY_SEALED";
+const SEALING_KEY_ENV: &str = "REDEEM_SEALING_KEY";
+const KEYRING_PATH_ENV: &str = "REDEEM_KEYRING_PATH";
+const TRUST_STORE_PATH_ENV: &str = "NOTARY_TRUST_STORE_PATH";
+const SCORING_CONFIG_ENV: &str = "SCORING_CONFIG";
+const REDEEM_STORE_PATH_ENV: &str = "REDEEM_STORE_PATH";
+const REPLAY_POLICY_ENV: &str = "REPLAY_POLICY";
+const LOG_FORMAT_ENV: &str = "LOG_FORMAT";
+const LOG_FILTER_ENV: &str = "RUST_LOG";
+const LOG_REDACT_SESSIONS_ENV: &str = "LOG_REDACT_SESSIONS";
+
+/// `--print-config` 输出中代替密钥的内容
+const REDACTED: &str = "<redacted>";
+
+/// 命令行参数, 优先级最高
+#[derive(Debug, Default, Parser)]
+#[command(
+    version,
+    about = "Verifies notarized Twitter sessions and issues signed redeem codes"
+)]
+pub struct Cli {
+    /// TOML 配置文件
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// 打印生效的配置 (隐去密钥) 后退出
+    #[arg(long)]
+    pub print_config: bool,
+    /// 打印签名私钥对应的公钥列表条目后退出, 只需要配置私钥
+    #[arg(long)]
+    pub print_public_key: bool,
+    /// 监听地址, 例如 `0.0.0.0:7049`
+    #[arg(long, value_name = "ADDR")]
+    pub listen: Option<String>,
+    /// 错误响应使用旧格式
+    #[arg(long)]
+    pub legacy_envelope: bool,
+    /// 请求体大小上限 (字节)
+    #[arg(long, value_name = "BYTES")]
+    pub max_body_bytes: Option<usize>,
+    #[arg(long, value_name = "PATH")]
+    pub signing_key_path: Option<PathBuf>,
+    #[arg(long, value_name = "PATH")]
+    pub signing_key_sealed: Option<PathBuf>,
+    #[arg(long, value_name = "PATH")]
+    pub keyring_path: Option<PathBuf>,
+    #[arg(long, value_name = "PATH")]
+    pub trust_store_path: Option<PathBuf>,
+    #[arg(long, value_name = "PATH")]
+    pub scoring_config: Option<PathBuf>,
+    #[arg(long, value_name = "PATH")]
+    pub redeem_store_path: Option<PathBuf>,
+    /// `return_existing` 或 `reject`
+    #[arg(long, value_name = "POLICY")]
+    pub replay_policy: Option<ReplayPolicy>,
+    /// `text` 或 `json`
+    #[arg(long, value_name = "FORMAT")]
+    pub log_format: Option<LogFormat>,
+}
+
+/// 服务配置, 优先级: 命令行参数 > 环境变量 > TOML 文件 > 默认值
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub keys: KeyConfig,
+    pub notary: NotaryConfig,
+    pub scoring: ScoringSettings,
+    pub storage: StorageConfig,
+    pub log: LogConfig,
+    /// session 必须来自的 host、接口与响应状态
+    pub request_target: RequestTargetPolicy,
+    /// 解析 notary 签名的 HTTP transcript 时的上限
+    pub transcript: TranscriptLimits,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub listen: String,
+    /// 错误响应使用旧格式, 见 [`crate::response::respond`]
+    pub legacy_envelope: bool,
+    pub max_body_bytes: usize,
+    /// 管理接口的 bearer token, 属于密钥; 不配置时管理接口不可用
+    pub admin_token: Option<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            listen: "0.0.0.0:7049".to_string(),
+            legacy_envelope: false,
+            // 与 axum 的默认值相同
+            max_body_bytes: 2 * 1024 * 1024,
+            admin_token: None,
+        }
+    }
+}
+
+/// 签名私钥必须且只能配置一种来源
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyConfig {
+    /// PEM 内容, 属于密钥
+    pub signing_key: Option<String>,
+    pub signing_key_path: Option<PathBuf>,
+    /// 加密的 PEM 文件, 需要同时配置 `sealing_key`
+    pub signing_key_sealed: Option<PathBuf>,
+    /// hex 编码的 32 字节密钥, 属于密钥
+    pub sealing_key: Option<String>,
+    /// 必须配置, 其中的 active 公钥必须与签名私钥对应
+    pub keyring_path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotaryConfig {
+    /// 必须配置, notary 的 url 与公钥因部署而异
+    pub trust_store_path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScoringSettings {
+    /// 评分 policy 与 campaign 规则, 不配置时使用内置配置
+    pub config_path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub redeem_store_path: PathBuf,
+    pub replay_policy: ReplayPolicy,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig {
+            redeem_store_path: PathBuf::from("redeem_codes.redb"),
+            replay_policy: ReplayPolicy::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub format: LogFormat,
+    /// `EnvFilter` 语法, 例如 `info,tee_verify=debug`
+    pub filter: String,
+    /// 日志中只记录 session 的 notary 与大小, 不记录推文内容和签名
+    pub redact_sessions: bool,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            format: LogFormat::default(),
+            filter: "info".to_string(),
+            redact_sessions: true,
+        }
+    }
+}
+
+fn config_error(message: String) -> ApiError {
+    ApiError::ConfigError(message)
+}
+
+impl Config {
+    /// 依次合并默认值、TOML 文件、环境变量与命令行参数, 然后校验
+    pub fn load(cli: &Cli, env: impl Fn(&str) -> Option<String>) -> Result<Self, ApiError> {
+        let config = Self::merge(cli, env)?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// 合并各层配置但不校验, 用于只需要部分配置的命令
+    pub fn merge(cli: &Cli, env: impl Fn(&str) -> Option<String>) -> Result<Self, ApiError> {
+        // 空字符串视为未设置
+        let env = |name: &str| env(name).filter(|value| !value.is_empty());
+
+        let config_path = cli
+            .config
+            .clone()
+            .or_else(|| env(CONFIG_PATH_ENV).map(PathBuf::from));
+        let mut config = match config_path {
+            Some(path) => Self::from_file(&path)?,
+            None => Config::default(),
+        };
+        config.apply_env(env)?;
+        config.apply_cli(cli);
+
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, ApiError> {
+        let text = std::fs::read_to_string(path).map_err(|err| {
+            config_error(format!(
+                "Cannot read config file {}: {}",
+                path.display(),
+                err
+            ))
+        })?;
+        Self::from_toml(&text)
+            .map_err(|err| config_error(format!("In config file {}: {}", path.display(), err)))
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, ApiError> {
+        toml::from_str(text).map_err(|err| config_error(format!("Invalid config: {}", err)))
+    }
+
+    fn apply_env(&mut self, env: impl Fn(&str) -> Option<String>) -> Result<(), ApiError> {
+        let parse_error = |name: &str, value: &str, expected: &str| {
+            config_error(format!(
+                "Environment variable {}={} is invalid: expected {}",
+                name, value, expected
+            ))
+        };
+
+        let parse_bool = |name: &str, value: &str| match value.to_ascii_lowercase().as_str() {
+            "1" | "true" => Ok(true),
+            "0" | "false" => Ok(false),
+            _ => Err(parse_error(name, value, "true or false")),
+        };
+
+        if let Some(listen) = env(LISTEN_ENV) {
+            self.server.listen = listen;
+        }
+        if let Some(value) = env(LEGACY_ENVELOPE_ENV) {
+            self.server.legacy_envelope = parse_bool(LEGACY_ENVELOPE_ENV, &value)?;
+        }
+        if let Some(value) = env(MAX_BODY_BYTES_ENV) {
+            self.server.max_body_bytes = value
+                .parse()
+                .map_err(|_| parse_error(MAX_BODY_BYTES_ENV, &value, "a number of bytes"))?;
+        }
+
+        if let Some(token) = env(ADMIN_TOKEN_ENV) {
+            self.server.admin_token = Some(token);
+        }
+        let signing_key = env(SIGNING_KEY_ENV);
+        let signing_key_path = env(SIGNING_KEY_PATH_ENV).map(PathBuf::from);
+        let signing_key_sealed = env(SIGNING_KEY_SEALED_ENV).map(PathBuf::from);
+        if signing_key.is_some() || signing_key_path.is_some() || signing_key_sealed.is_some() {
+            // 环境变量指定了私钥来源时替换配置文件中的来源, 而不是与之冲突
+            self.keys.signing_key = signing_key;
+            self.keys.signing_key_path = signing_key_path;
+            self.keys.signing_key_sealed = signing_key_sealed;
+        }
+        if let Some(sealing_key) = env(SEALING_KEY_ENV) {
+            self.keys.sealing_key = Some(sealing_key);
+        }
+        if let Some(path) = env(KEYRING_PATH_ENV) {
+            self.keys.keyring_path = Some(path.into());
+        }
+        if let Some(path) = env(TRUST_STORE_PATH_ENV) {
+            self.notary.trust_store_path = Some(path.into());
+        }
+        if let Some(path) = env(SCORING_CONFIG_ENV) {
+            self.scoring.config_path = Some(path.into());
+        }
+        if let Some(path) = env(REDEEM_STORE_PATH_ENV) {
+            self.storage.redeem_store_path = path.into();
+        }
+        if let Some(value) = env(REPLAY_POLICY_ENV) {
+            self.storage.replay_policy = value
+                .parse()
+                .map_err(|_| parse_error(REPLAY_POLICY_ENV, &value, "return_existing or reject"))?;
+        }
+        if let Some(value) = env(LOG_FORMAT_ENV) {
+            self.log.format = value
+                .parse()
+                .map_err(|_| parse_error(LOG_FORMAT_ENV, &value, "text or json"))?;
+        }
+        if let Some(filter) = env(LOG_FILTER_ENV) {
+            self.log.filter = filter;
+        }
+        if let Some(value) = env(LOG_REDACT_SESSIONS_ENV) {
+            self.log.redact_sessions = parse_bool(LOG_REDACT_SESSIONS_ENV, &value)?;
+        }
+
+        Ok(())
+    }
+
+    fn apply_cli(&mut self, cli: &Cli) {
+        if let Some(listen) = &cli.listen {
+            self.server.listen = listen.clone();
+        }
+        if cli.legacy_envelope {
+            self.server.legacy_envelope = true;
+        }
+        if let Some(max_body_bytes) = cli.max_body_bytes {
+            self.server.max_body_bytes = max_body_bytes;
+        }
+        if cli.signing_key_path.is_some() || cli.signing_key_sealed.is_some() {
+            self.keys.signing_key = None;
+            self.keys.signing_key_path = cli.signing_key_path.clone();
+            self.keys.signing_key_sealed = cli.signing_key_sealed.clone();
+        }
+        if let Some(path) = &cli.keyring_path {
+            self.keys.keyring_path = Some(path.clone());
+        }
+        if let Some(path) = &cli.trust_store_path {
+            self.notary.trust_store_path = Some(path.clone());
+        }
+        if let Some(path) = &cli.scoring_config {
+            self.scoring.config_path = Some(path.clone());
+        }
+        if let Some(path) = &cli.redeem_store_path {
+            self.storage.redeem_store_path = path.clone();
+        }
+        if let Some(replay_policy) = cli.replay_policy {
+            self.storage.replay_policy = replay_policy;
+        }
+        if let Some(log_format) = cli.log_format {
+            self.log.format = log_format;
+        }
+    }
+
+    /// 启动前检查, 错误信息中给出对应的配置项
+    pub fn validate(&self) -> Result<(), ApiError> {
+        self.listen_addr()?;
+        if self.server.max_body_bytes == 0 {
+            return Err(config_error(
+                "server.max_body_bytes must be positive".to_string(),
+            ));
+        }
+        self.keys.signing_key_source()?;
+        self.request_target.validate()?;
+        self.transcript.validate()?;
+        if self.keys.keyring_path.is_none() {
+            return Err(config_error("keys.keyring_path must be set".to_string()));
+        }
+        if self.notary.trust_store_path.is_none() {
+            return Err(config_error(
+                "notary.trust_store_path must be set".to_string(),
+            ));
+        }
+        if let Err(err) = tracing_subscriber::EnvFilter::try_new(&self.log.filter) {
+            return Err(config_error(format!(
+                "log.filter {} is invalid: {}",
+                self.log.filter, err
+            )));
+        }
+
+        let files = [
+            ("keys.signing_key_path", &self.keys.signing_key_path),
+            ("keys.signing_key_sealed", &self.keys.signing_key_sealed),
+            ("keys.keyring_path", &self.keys.keyring_path),
+            ("notary.trust_store_path", &self.notary.trust_store_path),
+            ("scoring.config_path", &self.scoring.config_path),
+        ];
+        for (name, path) in files {
+            if let Some(path) = path.as_ref().filter(|path| !path.is_file()) {
+                return Err(config_error(format!(
+                    "{} {} does not exist or is not a file",
+                    name,
+                    path.display()
+                )));
+            }
+        }
+
+        let store_dir = self
+            .storage
+            .redeem_store_path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty());
+        if let Some(dir) = store_dir.filter(|dir| !dir.is_dir()) {
+            return Err(config_error(format!(
+                "storage.redeem_store_path directory {} does not exist",
+                dir.display()
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub fn listen_addr(&self) -> Result<SocketAddr, ApiError> {
+        self.server.listen.parse().map_err(|_| {
+            config_error(format!(
+                "server.listen {} is not a valid socket address such as 0.0.0.0:7049",
+                self.server.listen
+            ))
+        })
+    }
+
+    /// 隐去密钥后的配置, 用于 `--print-config`
+    pub fn redacted(&self) -> Self {
+        let redact = |secret: &Option<String>| secret.as_ref().map(|_| REDACTED.to_string());
+
+        let mut config = self.clone();
+        config.keys.signing_key = redact(&self.keys.signing_key);
+        config.keys.sealing_key = redact(&self.keys.sealing_key);
+        config.server.admin_token = redact(&self.server.admin_token);
+        config
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string_pretty(self).expect("config is always representable as TOML")
+    }
+}
+
+impl KeyConfig {
+    pub fn signing_key_source(&self) -> Result<SigningKeySource, ApiError> {
+        let sealed = match (&self.signing_key_sealed, &self.sealing_key) {
+            (Some(path), Some(sealing_key)) => Some(SigningKeySource::Sealed {
+                path: path.clone(),
+                sealing_key: sealing_key.clone(),
+            }),
+            (Some(_), None) => {
+                return Err(config_error(format!(
+                    "keys.signing_key_sealed requires keys.sealing_key ({})",
+                    SEALING_KEY_ENV
+                )))
+            }
+            (None, _) => None,
+        };
+        let sources: Vec<SigningKeySource> = [
+            self.signing_key.clone().map(SigningKeySource::Inline),
+            self.signing_key_path.clone().map(SigningKeySource::Path),
+            sealed,
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        match <[SigningKeySource; 1]>::try_from(sources) {
+            Ok([source]) => Ok(source),
+            Err(sources) => Err(config_error(format!(
+                "Exactly one of keys.signing_key ({}), keys.signing_key_path ({}) or \
+                 keys.signing_key_sealed ({}) must be set, found {}",
+                SIGNING_KEY_ENV,
+                SIGNING_KEY_PATH_ENV,
+                SIGNING_KEY_SEALED_ENV,
+                sources.len()
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::handler::twitter::redeem_keys::{test_keyring_json, test_signing_key_bs58_pem};
+
+    const TRUST_STORE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/src/notary/trust_store.example.json"
+    );
+
+    fn load(cli: &Cli, env: &[(&str, &str)]) -> Result<Config, ApiError> {
+        let env: HashMap<String, String> = env
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        Config::load(cli, |name| env.get(name).cloned())
+    }
+
+    fn config_file(name: &str, toml: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("tee-verify-{}-{}.toml", name, std::process::id()));
+        std::fs::write(&path, toml).unwrap();
+        path
+    }
+
+    /// 测试私钥与公钥列表写入临时文件, 返回两个路径
+    fn key_files(name: &str) -> (String, String) {
+        let dir = std::env::temp_dir();
+        let private_pem = dir.join(format!("tee-verify-{}-{}.pem", name, std::process::id()));
+        let keyring = dir.join(format!("tee-verify-{}-{}.json", name, std::process::id()));
+        std::fs::write(&private_pem, test_signing_key_bs58_pem()).unwrap();
+        std::fs::write(&keyring, test_keyring_json()).unwrap();
+        (
+            private_pem.to_str().unwrap().to_string(),
+            keyring.to_str().unwrap().to_string(),
+        )
+    }
+
+    #[test]
+    fn test_layers() {
+        let (private_pem, keyring) = key_files("layers");
+        let path = config_file(
+            "layers",
+            &format!(
+                r#"
+                [server]
+                listen = "127.0.0.1:5000"
+                max_body_bytes = 1024
+
+                [keys]
+                signing_key_path = "{}"
+                keyring_path = "{}"
+
+                [notary]
+                trust_store_path = "{}"
+
+                [storage]
+                replay_policy = "reject"
+
+                [request_target]
+                allowed_hosts = ["x.com"]
+
+                [transcript]
+                max_headers = 64
+                "#,
+                private_pem, keyring, TRUST_STORE
+            ),
+        );
+
+        // 只有配置文件
+        let cli = Cli {
+            config: Some(path.clone()),
+            ..Cli::default()
+        };
+        let config = load(&cli, &[]).unwrap();
+        assert_eq!(config.server.listen, "127.0.0.1:5000");
+        assert_eq!(config.server.max_body_bytes, 1024);
+        assert_eq!(config.storage.replay_policy, ReplayPolicy::Reject);
+        assert_eq!(config.request_target.allowed_hosts, ["x.com"]);
+        assert_eq!(config.request_target.allowed_statuses, [200]);
+        assert_eq!(config.transcript.max_headers, 64);
+        assert_eq!(
+            config.transcript.max_decoded_body_bytes,
+            TranscriptLimits::default().max_decoded_body_bytes
+        );
+        assert_eq!(
+            config.storage.redeem_store_path,
+            PathBuf::from("redeem_codes.redb")
+        );
+
+        // 环境变量覆盖配置文件, 私钥来源整体替换
+        let env = [
+            (LISTEN_ENV, "127.0.0.1:6000"),
+            (SIGNING_KEY_ENV, "pem"),
+            (LEGACY_ENVELOPE_ENV, "true"),
+            (LOG_FORMAT_ENV, "json"),
+            (LOG_REDACT_SESSIONS_ENV, "0"),
+        ];
+        let config = load(&cli, &env).unwrap();
+        assert_eq!(config.server.listen, "127.0.0.1:6000");
+        assert!(config.server.legacy_envelope);
+        assert_eq!(config.log.format, LogFormat::Json);
+        assert!(!config.log.redact_sessions);
+        assert_eq!(config.keys.signing_key.as_deref(), Some("pem"));
+        assert_eq!(config.keys.signing_key_path, None);
+
+        // 命令行参数优先级最高
+        let cli = Cli {
+            listen: Some("127.0.0.1:7000".to_string()),
+            signing_key_path: Some(private_pem.into()),
+            ..cli
+        };
+        let config = load(&cli, &env).unwrap();
+        assert_eq!(config.server.listen, "127.0.0.1:7000");
+        assert_eq!(config.keys.signing_key, None);
+        assert!(matches!(
+            config.keys.signing_key_source().unwrap(),
+            SigningKeySource::Path(_)
+        ));
+
+        // 通过环境变量指定配置文件
+        let config = load(
+            &Cli::default(),
+            &[(CONFIG_PATH_ENV, path.to_str().unwrap())],
+        )
+        .unwrap();
+        assert_eq!(config.server.listen, "127.0.0.1:5000");
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let (private_pem, keyring) = key_files("invalid");
+        let key = (SIGNING_KEY_ENV, "pem");
+        let keyring = (KEYRING_PATH_ENV, keyring.as_str());
+        let trust_store = (TRUST_STORE_PATH_ENV, TRUST_STORE);
+        let cases: [&[(&str, &str)]; 12] = [
+            // 没有私钥
+            &[keyring, trust_store],
+            // 两个私钥来源
+            &[
+                key,
+                keyring,
+                trust_store,
+                (SIGNING_KEY_PATH_ENV, private_pem.as_str()),
+            ],
+            // 加密的私钥缺少解密密钥
+            &[
+                keyring,
+                trust_store,
+                (SIGNING_KEY_SEALED_ENV, private_pem.as_str()),
+            ],
+            // 没有公钥列表
+            &[key, trust_store],
+            &[
+                key,
+                trust_store,
+                (KEYRING_PATH_ENV, "/nonexistent/keyring.json"),
+            ],
+            // 没有 notary 信任列表
+            &[key, keyring],
+            &[key, keyring, trust_store, (LISTEN_ENV, "localhost")],
+            &[key, keyring, trust_store, (MAX_BODY_BYTES_ENV, "0")],
+            &[key, keyring, trust_store, (REPLAY_POLICY_ENV, "ignore")],
+            &[
+                key,
+                keyring,
+                (TRUST_STORE_PATH_ENV, "/nonexistent/trust_store.json"),
+            ],
+            &[key, keyring, trust_store, (LOG_FORMAT_ENV, "yaml")],
+            &[
+                key,
+                keyring,
+                trust_store,
+                (LOG_FILTER_ENV, "tee_verify=loud"),
+            ],
+        ];
+        for env in cases {
+            assert!(
+                matches!(load(&Cli::default(), env), Err(ApiError::ConfigError(_))),
+                "{:?} should be rejected",
+                env
+            );
+        }
+
+        // 配置文件中的未知字段
+        assert!(matches!(
+            Config::from_toml("[server]\nport = 5000"),
+            Err(ApiError::ConfigError(_))
+        ));
+        assert!(matches!(
+            Config::from_toml("[request_target]\nhosts = [\"x.com\"]"),
+            Err(ApiError::ConfigError(_))
+        ));
+
+        // 空的 request_target 会拒绝所有 session
+        let mut config = load(&Cli::default(), &[key, keyring, trust_store]).unwrap();
+        config.request_target.allowed_hosts.clear();
+        assert!(matches!(config.validate(), Err(ApiError::ConfigError(_))));
+
+        let mut config = load(&Cli::default(), &[key, keyring, trust_store]).unwrap();
+        config.transcript.max_decoded_body_bytes = 0;
+        assert!(matches!(config.validate(), Err(ApiError::ConfigError(_))));
+    }
+
+    #[test]
+    fn test_redacted() {
+        let (private_pem, keyring) = key_files("redacted");
+        let config = load(
+            &Cli::default(),
+            &[
+                (SIGNING_KEY_SEALED_ENV, private_pem.as_str()),
+                (KEYRING_PATH_ENV, keyring.as_str()),
+                (SEALING_KEY_ENV, "00112233"),
+                (TRUST_STORE_PATH_ENV, TRUST_STORE),
+                (ADMIN_TOKEN_ENV, "admin-secret"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.server.admin_token.as_deref(), Some("admin-secret"));
+
+        let printed = config.redacted().to_toml();
+        assert!(!printed.contains("00112233"));
+        assert!(!printed.contains("admin-secret"));
+        assert!(printed.contains(REDACTED));
+        assert!(printed.contains("listen = \"0.0.0.0:7049\""));
+
+        // 打印出的配置可以再次读取
+        let reparsed = Config::from_toml(&printed).unwrap();
+        assert_eq!(reparsed.server, config.redacted().server);
+        assert_eq!(reparsed.storage, config.storage);
+        assert_eq!(reparsed.request_target, config.request_target);
+        assert_eq!(reparsed.transcript, config.transcript);
+    }
+}
@@ -2,6 +2,9 @@ use std::fmt;
 
 use axum::{http::StatusCode, response::IntoResponse, Json};
 use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::response::ErrorResponse;
 
 #[derive(Debug)]
 pub enum ApiError {
@@ -11,12 +14,143 @@ pub enum ApiError {
     SignatureError(String),
     // 无效的消息
     InvalidMessage(String),
+    // 请求体或 session 不是合法的 JSON
+    InvalidJson(String),
+    // session 签名与 application_data 不匹配
+    SessionSignatureError(String),
+    // session 声明的 notary 不在信任列表中
+    UntrustedNotary(String),
+    // attribute 与 notary 签名的 transcript 不一致
+    TranscriptMismatch(String),
+    // 无法解析的 HTTP transcript
+    InvalidTranscript(String),
+    // notary 签名的请求不是我们要求的接口
+    InvalidRequestTarget(String),
+    // 推文作者与登录用户不一致
+    AuthorMismatch(String),
+    // 推文中没有 CA 地址
+    CaNotFound(String),
+    // 兑换码格式错误
+    InvalidRedeemCode(String),
+    // 服务端签名私钥无法加载
+    SigningKeyError(String),
+    // 服务端配置无效
+    ConfigError(String),
+    // 兑换码存储读写失败
+    StoreError(String),
+    // 该推文已经签发过兑换码
+    AlreadyRedeemed(String),
+    // 兑换码已过期
+    RedeemCodeExpired(String),
+    // 兑换码还没到生效时间
+    RedeemCodeNotYetValid(String),
+    // 推文发布时间不在 campaign 要求的范围内
+    TweetOutsideWindow(String),
+    // 互动分数低于 policy 的 min_score
+    EngagementTooLow(String),
+    // 兑换码已经兑换过
+    RedeemCodeConsumed(String),
+    // 兑换码已被作废
+    RedeemCodeRevoked(String),
+    // 管理接口缺少或使用了错误的 token
+    Unauthorized(String),
 }
 
-#[derive(Serialize)]
-pub struct ErrorResponse {
-    code: u16,
-    message: String,
+/// 响应中机器可读的错误码, 序列化后的名字是对外接口的一部分, 不能修改
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    NotFound,
+    SignatureInvalid,
+    InvalidMessage,
+    InvalidJson,
+    SessionSignatureInvalid,
+    UntrustedNotary,
+    TranscriptMismatch,
+    InvalidTranscript,
+    InvalidRequestTarget,
+    AuthorMismatch,
+    CaNotFound,
+    InvalidRedeemCode,
+    SigningKeyError,
+    ConfigError,
+    StoreError,
+    AlreadyRedeemed,
+    RedeemCodeExpired,
+    RedeemCodeNotYetValid,
+    TweetOutsideWindow,
+    EngagementTooLow,
+    RedeemCodeConsumed,
+    RedeemCodeRevoked,
+    Unauthorized,
+}
+
+/// 与响应中的名字一致, 例如 `SIGNATURE_INVALID`
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match serde_json::to_value(self) {
+            Ok(serde_json::Value::String(name)) => f.write_str(&name),
+            _ => Err(fmt::Error),
+        }
+    }
+}
+
+impl ApiError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ApiError::NotFound(_) => ErrorCode::NotFound,
+            ApiError::SignatureError(_) => ErrorCode::SignatureInvalid,
+            ApiError::InvalidMessage(_) => ErrorCode::InvalidMessage,
+            ApiError::InvalidJson(_) => ErrorCode::InvalidJson,
+            ApiError::SessionSignatureError(_) => ErrorCode::SessionSignatureInvalid,
+            ApiError::UntrustedNotary(_) => ErrorCode::UntrustedNotary,
+            ApiError::TranscriptMismatch(_) => ErrorCode::TranscriptMismatch,
+            ApiError::InvalidTranscript(_) => ErrorCode::InvalidTranscript,
+            ApiError::InvalidRequestTarget(_) => ErrorCode::InvalidRequestTarget,
+            ApiError::AuthorMismatch(_) => ErrorCode::AuthorMismatch,
+            ApiError::CaNotFound(_) => ErrorCode::CaNotFound,
+            ApiError::InvalidRedeemCode(_) => ErrorCode::InvalidRedeemCode,
+            ApiError::SigningKeyError(_) => ErrorCode::SigningKeyError,
+            ApiError::ConfigError(_) => ErrorCode::ConfigError,
+            ApiError::StoreError(_) => ErrorCode::StoreError,
+            ApiError::AlreadyRedeemed(_) => ErrorCode::AlreadyRedeemed,
+            ApiError::RedeemCodeExpired(_) => ErrorCode::RedeemCodeExpired,
+            ApiError::RedeemCodeNotYetValid(_) => ErrorCode::RedeemCodeNotYetValid,
+            ApiError::TweetOutsideWindow(_) => ErrorCode::TweetOutsideWindow,
+            ApiError::EngagementTooLow(_) => ErrorCode::EngagementTooLow,
+            ApiError::RedeemCodeConsumed(_) => ErrorCode::RedeemCodeConsumed,
+            ApiError::RedeemCodeRevoked(_) => ErrorCode::RedeemCodeRevoked,
+            ApiError::Unauthorized(_) => ErrorCode::Unauthorized,
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::SignatureError(_) => StatusCode::UNAUTHORIZED,
+            ApiError::InvalidMessage(_) => StatusCode::BAD_REQUEST,
+            ApiError::InvalidJson(_) => StatusCode::BAD_REQUEST,
+            ApiError::SessionSignatureError(_) => StatusCode::UNAUTHORIZED,
+            ApiError::UntrustedNotary(_) => StatusCode::FORBIDDEN,
+            ApiError::TranscriptMismatch(_) => StatusCode::UNAUTHORIZED,
+            ApiError::InvalidTranscript(_) => StatusCode::BAD_REQUEST,
+            ApiError::InvalidRequestTarget(_) => StatusCode::BAD_REQUEST,
+            ApiError::AuthorMismatch(_) => StatusCode::FORBIDDEN,
+            ApiError::CaNotFound(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::InvalidRedeemCode(_) => StatusCode::BAD_REQUEST,
+            ApiError::SigningKeyError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::ConfigError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::StoreError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::AlreadyRedeemed(_) => StatusCode::CONFLICT,
+            ApiError::RedeemCodeExpired(_) => StatusCode::GONE,
+            ApiError::RedeemCodeNotYetValid(_) => StatusCode::FORBIDDEN,
+            ApiError::TweetOutsideWindow(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::EngagementTooLow(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::RedeemCodeConsumed(_) => StatusCode::CONFLICT,
+            ApiError::RedeemCodeRevoked(_) => StatusCode::GONE,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+        }
+    }
 }
 
 impl fmt::Display for ApiError {
@@ -25,21 +159,41 @@ impl fmt::Display for ApiError {
             ApiError::NotFound(msg) => write!(f, "Not Found : {}", msg),
             ApiError::SignatureError(msg) => write!(f, "Signature Error: {}", msg),
             ApiError::InvalidMessage(msg) => write!(f, "Invalid Message: {}", msg),
+            ApiError::InvalidJson(msg) => write!(f, "Invalid JSON: {}", msg),
+            ApiError::SessionSignatureError(msg) => write!(f, "Session Signature Error: {}", msg),
+            ApiError::UntrustedNotary(msg) => write!(f, "Untrusted Notary: {}", msg),
+            ApiError::TranscriptMismatch(msg) => write!(f, "Transcript Mismatch: {}", msg),
+            ApiError::InvalidTranscript(msg) => write!(f, "Invalid Transcript: {}", msg),
+            ApiError::InvalidRequestTarget(msg) => write!(f, "Invalid Request Target: {}", msg),
+            ApiError::AuthorMismatch(msg) => write!(f, "Author Mismatch: {}", msg),
+            ApiError::CaNotFound(msg) => write!(f, "CA Not Found: {}", msg),
+            ApiError::InvalidRedeemCode(msg) => write!(f, "Invalid Redeem Code: {}", msg),
+            ApiError::SigningKeyError(msg) => write!(f, "Signing Key Error: {}", msg),
+            ApiError::ConfigError(msg) => write!(f, "Config Error: {}", msg),
+            ApiError::StoreError(msg) => write!(f, "Store Error: {}", msg),
+            ApiError::AlreadyRedeemed(msg) => write!(f, "Already Redeemed: {}", msg),
+            ApiError::RedeemCodeExpired(msg) => write!(f, "Redeem Code Expired: {}", msg),
+            ApiError::RedeemCodeNotYetValid(msg) => write!(f, "Redeem Code Not Yet Valid: {}", msg),
+            ApiError::TweetOutsideWindow(msg) => write!(f, "Tweet Outside Window: {}", msg),
+            ApiError::EngagementTooLow(msg) => write!(f, "Engagement Too Low: {}", msg),
+            ApiError::RedeemCodeConsumed(msg) => write!(f, "Redeem Code Consumed: {}", msg),
+            ApiError::RedeemCodeRevoked(msg) => write!(f, "Redeem Code Revoked: {}", msg),
+            ApiError::Unauthorized(msg) => write!(f, "Unauthorized: {}", msg),
         }
     }
 }
 
+impl std::error::Error for ApiError {}
+
+/// 使用真实的 HTTP 状态码, 响应体见 [`ErrorResponse`]
 impl IntoResponse for ApiError {
     fn into_response(self) -> axum::response::Response {
-        let (status, message) = match self {
-            ApiError::NotFound(msg) => (StatusCode::NOT_FOUND, msg),
-            ApiError::SignatureError(msg) => (StatusCode::UNAUTHORIZED, msg),
-            ApiError::InvalidMessage(msg) => (StatusCode::BAD_REQUEST, msg),
-        };
-
+        let status = self.status();
         let body = Json(ErrorResponse {
             code: status.as_u16(),
-            message,
+            error: self.code(),
+            message: self.to_string(),
+            data: None,
         });
 
         (status, body).into_response()
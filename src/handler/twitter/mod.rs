@@ -1,5 +1,29 @@
 pub mod verify_and_sign;
 // pub use verify_and_sign::*;
 
+pub mod verify_request;
+// pub use verify_request::*;
+
+pub mod request_target;
+// pub use request_target::*;
+
+pub mod tweet_claims;
+// pub use tweet_claims::*;
+
 pub mod generate_redeemcode_and_sign;
 // pub use generate_redeemcode_and_sign::*;
+
+pub mod scoring_policy;
+// pub use scoring_policy::*;
+
+pub mod redeem_keys;
+// pub use redeem_keys::*;
+
+pub mod verify_redeemcode;
+// pub use verify_redeemcode::*;
+
+pub mod redeem_code;
+// pub use redeem_code::*;
+
+pub mod redeem_code_v2;
+// pub use redeem_code_v2::*;
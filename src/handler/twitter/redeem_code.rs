@@ -0,0 +1,170 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::ApiError;
+use crate::utils::is_valid_ca;
+
+use super::generate_redeemcode_and_sign::{RedeemCodeVersion, CLIENT};
+
+/// key ID 为公钥 sha256 前 4 字节的 hex
+const KEY_ID_LEN: usize = 8;
+
+/// v1 兑换码 `v1-<client>-<post id>-<ca>-<engagement>-<key id>`
+///
+/// 轮换之前签发的兑换码没有 key ID, 解析和格式化时都保持原样
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedeemCode {
+    pub client: String,
+    pub post_id: String,
+    pub ca: String,
+    pub engagement: u64,
+    pub key_id: Option<String>,
+}
+
+fn invalid_code(reason: &str) -> ApiError {
+    ApiError::InvalidRedeemCode(reason.to_string())
+}
+
+fn is_decimal(value: &str) -> bool {
+    !value.is_empty() && value.chars().all(|c| c.is_ascii_digit())
+}
+
+impl fmt::Display for RedeemCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}-{}-{}-{}",
+            RedeemCodeVersion::V1.as_str(),
+            self.client,
+            self.post_id,
+            self.ca,
+            self.engagement
+        )?;
+        if let Some(key_id) = &self.key_id {
+            write!(f, "-{}", key_id)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for RedeemCode {
+    type Err = ApiError;
+
+    fn from_str(redeemcode: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = redeemcode.split('-').collect();
+        let (version, client, post_id, ca, engagement, key_id) = match parts[..] {
+            [version, client, post_id, ca, engagement] => {
+                (version, client, post_id, ca, engagement, None)
+            }
+            [version, client, post_id, ca, engagement, key_id] => {
+                (version, client, post_id, ca, engagement, Some(key_id))
+            }
+            _ => return Err(invalid_code("expected 5 or 6 components")),
+        };
+
+        if version != RedeemCodeVersion::V1.as_str() {
+            return Err(invalid_code("unsupported version"));
+        }
+        if client != CLIENT {
+            return Err(invalid_code("unsupported client"));
+        }
+        if !is_decimal(post_id) {
+            return Err(invalid_code("post ID must be numeric"));
+        }
+        if ca.is_empty() {
+            return Err(invalid_code("missing CA"));
+        }
+        if !is_valid_ca(ca) {
+            return Err(invalid_code("CA is not a valid address"));
+        }
+        // 不接受 `+1`、`01` 这类写法, 保证格式化后与原文一致
+        if !is_decimal(engagement) || (engagement.len() > 1 && engagement.starts_with('0')) {
+            return Err(invalid_code("engagement must be an unsigned integer"));
+        }
+        let engagement = engagement
+            .parse::<u64>()
+            .map_err(|_| invalid_code("engagement is out of range"))?;
+        if key_id.is_some_and(str::is_empty) {
+            return Err(invalid_code("missing key ID"));
+        }
+        if key_id.is_some_and(|key_id| {
+            key_id.len() != KEY_ID_LEN
+                || !key_id
+                    .chars()
+                    .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+        }) {
+            return Err(invalid_code("key ID must be 8 lowercase hex characters"));
+        }
+
+        Ok(RedeemCode {
+            client: client.to_string(),
+            post_id: post_id.to_string(),
+            ca: ca.to_string(),
+            engagement,
+            key_id: key_id.map(str::to_string),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CA: &str = "6p6xgHyF7AeE6TZkSmFsko444wqoP15icUSqi2jfGiPN";
+
+    #[test]
+    fn test_round_trip() {
+        let redeemcodes = [
+            format!("v1-twitter-111111111111111-{}-1-3a0e06ba", CA),
+            format!("v1-twitter-111111111111111-{}-0", CA),
+            "v1-twitter-1-0x85e58d0f9152669083bda1e6638fa6400898d0ee-18446744073709551615"
+                .to_string(),
+        ];
+        for redeemcode in redeemcodes {
+            let parsed = redeemcode.parse::<RedeemCode>().unwrap();
+            assert_eq!(parsed.to_string(), redeemcode);
+        }
+
+        let parsed = format!("v1-twitter-42-{}-7-3a0e06ba", CA)
+            .parse::<RedeemCode>()
+            .unwrap();
+        assert_eq!(
+            parsed,
+            RedeemCode {
+                client: "twitter".to_string(),
+                post_id: "42".to_string(),
+                ca: CA.to_string(),
+                engagement: 7,
+                key_id: Some("3a0e06ba".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn test_rejects_invalid_components() {
+        let cases = [
+            format!("v1-twitter-42-{}", CA),
+            format!("v1-twitter-42-{}-1-3a0e06ba-extra", CA),
+            format!("v2-twitter-42-{}-1", CA),
+            format!("v1-tiktok-42-{}-1", CA),
+            format!("v1-twitter-4a-{}-1", CA),
+            "v1-twitter-42-not_a_ca-1".to_string(),
+            format!("v1-twitter-42-{}-+1", CA),
+            format!("v1-twitter-42-{}-01", CA),
+            format!("v1-twitter-42-{}-18446744073709551616", CA),
+            format!("v1-twitter-42-{}-1-", CA),
+            format!("v1-twitter-42-{}-1-3A0E06BA", CA),
+            format!("v1-twitter-42-{}-1-3a0e06", CA),
+        ];
+        for redeemcode in cases {
+            assert!(
+                matches!(
+                    redeemcode.parse::<RedeemCode>(),
+                    Err(ApiError::InvalidRedeemCode(_))
+                ),
+                "{} should be rejected",
+                redeemcode
+            );
+        }
+    }
+}
@@ -0,0 +1,303 @@
+use ciborium::Value;
+
+use crate::error::ApiError;
+
+/// v2 兑换码的文本形式: `v2-<bs58check(cbor)>`
+pub const V2_PREFIX: &str = "v2-";
+pub const NONCE_LEN: usize = 16;
+
+// CBOR map 中各字段的整数 key, 编码时按升序排列, 可选字段为空时省略
+const KEY_CLIENT: u64 = 1;
+const KEY_POST_ID: u64 = 2;
+const KEY_CA: u64 = 3;
+const KEY_ENGAGEMENT: u64 = 4;
+const KEY_ISSUED_AT: u64 = 5;
+const KEY_EXPIRES_AT: u64 = 6;
+const KEY_NONCE: u64 = 7;
+const KEY_CAMPAIGN: u64 = 8;
+const KEY_POLICY_ID: u64 = 9;
+const KEY_KEY_ID: u64 = 10;
+const KEY_NOT_BEFORE: u64 = 11;
+
+/// v2 兑换码, 编码为确定性的 CBOR (整数 key 升序、最短编码), 文本形式带校验和
+///
+/// 解析时忽略未知的 key, 之后新增字段不会影响旧的解析器
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedeemCodeV2 {
+    pub client: String,
+    pub post_id: String,
+    pub ca: String,
+    pub engagement: u64,
+    /// 签发时间 (unix 秒)
+    pub issued_at: u64,
+    /// 过期时间 (unix 秒), 没有时长期有效
+    pub expires_at: Option<u64>,
+    /// 生效时间 (unix 秒), 没有时签发后立即生效
+    pub not_before: Option<u64>,
+    /// 随机数, 保证同样内容的兑换码也互不相同
+    pub nonce: [u8; NONCE_LEN],
+    pub campaign: Option<String>,
+    pub policy_id: String,
+    pub key_id: String,
+}
+
+fn invalid_code(reason: &str) -> ApiError {
+    ApiError::InvalidRedeemCode(reason.to_string())
+}
+
+impl RedeemCodeV2 {
+    pub fn to_cbor(&self) -> Vec<u8> {
+        let text = |value: &str| Value::Text(value.to_string());
+        let integer = |value: u64| Value::Integer(value.into());
+
+        let fields = [
+            (KEY_CLIENT, Some(text(&self.client))),
+            (KEY_POST_ID, Some(text(&self.post_id))),
+            (KEY_CA, Some(text(&self.ca))),
+            (KEY_ENGAGEMENT, Some(integer(self.engagement))),
+            (KEY_ISSUED_AT, Some(integer(self.issued_at))),
+            (KEY_EXPIRES_AT, self.expires_at.map(integer)),
+            (KEY_NONCE, Some(Value::Bytes(self.nonce.to_vec()))),
+            (KEY_CAMPAIGN, self.campaign.as_deref().map(text)),
+            (KEY_POLICY_ID, Some(text(&self.policy_id))),
+            (KEY_KEY_ID, Some(text(&self.key_id))),
+            (KEY_NOT_BEFORE, self.not_before.map(integer)),
+        ];
+        let map = fields
+            .into_iter()
+            .filter_map(|(key, value)| value.map(|value| (integer(key), value)))
+            .collect();
+
+        let mut cbor = Vec::new();
+        ciborium::into_writer(&Value::Map(map), &mut cbor)
+            .expect("writing CBOR to a Vec cannot fail");
+        cbor
+    }
+
+    pub fn from_cbor(cbor: &[u8]) -> Result<Self, ApiError> {
+        let value: Value =
+            ciborium::from_reader(cbor).map_err(|_| invalid_code("payload is not valid CBOR"))?;
+
+        // 重新编码必须得到相同的字节, 保证只有一种合法的编码
+        let mut canonical = Vec::new();
+        ciborium::into_writer(&value, &mut canonical)
+            .map_err(|_| invalid_code("payload is not valid CBOR"))?;
+        if canonical != cbor {
+            return Err(invalid_code("payload is not canonical CBOR"));
+        }
+
+        let Value::Map(entries) = value else {
+            return Err(invalid_code("payload must be a CBOR map"));
+        };
+
+        let mut code = RedeemCodeV2Builder::default();
+        let mut last_key = None;
+        for (key, value) in entries {
+            let key = key
+                .as_integer()
+                .and_then(|key| u64::try_from(key).ok())
+                .ok_or_else(|| invalid_code("map keys must be unsigned integers"))?;
+            if last_key.is_some_and(|last_key| key <= last_key) {
+                return Err(invalid_code("map keys must be in ascending order"));
+            }
+            last_key = Some(key);
+
+            match key {
+                KEY_CLIENT => code.client = Some(into_text(value)?),
+                KEY_POST_ID => code.post_id = Some(into_text(value)?),
+                KEY_CA => code.ca = Some(into_text(value)?),
+                KEY_ENGAGEMENT => code.engagement = Some(into_u64(value)?),
+                KEY_ISSUED_AT => code.issued_at = Some(into_u64(value)?),
+                KEY_EXPIRES_AT => code.expires_at = Some(into_u64(value)?),
+                KEY_NONCE => {
+                    code.nonce = Some(
+                        value
+                            .into_bytes()
+                            .ok()
+                            .and_then(|nonce| nonce.try_into().ok())
+                            .ok_or_else(|| invalid_code("nonce must be 16 bytes"))?,
+                    )
+                }
+                KEY_CAMPAIGN => code.campaign = Some(into_text(value)?),
+                KEY_POLICY_ID => code.policy_id = Some(into_text(value)?),
+                KEY_KEY_ID => code.key_id = Some(into_text(value)?),
+                KEY_NOT_BEFORE => code.not_before = Some(into_u64(value)?),
+                _ => {}
+            }
+        }
+
+        code.build()
+    }
+
+    /// `v2-` 加上带校验和的 bs58 编码
+    pub fn encode(&self) -> String {
+        format!(
+            "{}{}",
+            V2_PREFIX,
+            bs58::encode(self.to_cbor()).with_check().into_string()
+        )
+    }
+
+    pub fn decode(redeemcode: &str) -> Result<Self, ApiError> {
+        let payload = redeemcode
+            .strip_prefix(V2_PREFIX)
+            .ok_or_else(|| invalid_code("missing v2 prefix"))?;
+        let cbor = bs58::decode(payload)
+            .with_check(None)
+            .into_vec()
+            .map_err(|_| invalid_code("checksum mismatch or invalid base58"))?;
+
+        Self::from_cbor(&cbor)
+    }
+}
+
+#[derive(Default)]
+struct RedeemCodeV2Builder {
+    client: Option<String>,
+    post_id: Option<String>,
+    ca: Option<String>,
+    engagement: Option<u64>,
+    issued_at: Option<u64>,
+    expires_at: Option<u64>,
+    not_before: Option<u64>,
+    nonce: Option<[u8; NONCE_LEN]>,
+    campaign: Option<String>,
+    policy_id: Option<String>,
+    key_id: Option<String>,
+}
+
+impl RedeemCodeV2Builder {
+    fn build(self) -> Result<RedeemCodeV2, ApiError> {
+        let missing = |field: &str| invalid_code(&format!("missing {}", field));
+
+        Ok(RedeemCodeV2 {
+            client: self.client.ok_or_else(|| missing("client"))?,
+            post_id: self.post_id.ok_or_else(|| missing("post ID"))?,
+            ca: self.ca.ok_or_else(|| missing("CA"))?,
+            engagement: self.engagement.ok_or_else(|| missing("engagement"))?,
+            issued_at: self.issued_at.ok_or_else(|| missing("issued at"))?,
+            expires_at: self.expires_at,
+            not_before: self.not_before,
+            nonce: self.nonce.ok_or_else(|| missing("nonce"))?,
+            campaign: self.campaign,
+            policy_id: self.policy_id.ok_or_else(|| missing("policy ID"))?,
+            key_id: self.key_id.ok_or_else(|| missing("key ID"))?,
+        })
+    }
+}
+
+fn into_text(value: Value) -> Result<String, ApiError> {
+    value
+        .into_text()
+        .map_err(|_| invalid_code("expected a text field"))
+}
+
+fn into_u64(value: Value) -> Result<u64, ApiError> {
+    value
+        .as_integer()
+        .and_then(|value| u64::try_from(value).ok())
+        .ok_or_else(|| invalid_code("expected an unsigned integer field"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code() -> RedeemCodeV2 {
+        RedeemCodeV2 {
+            client: "twitter".to_string(),
+            post_id: "1879456397454385265".to_string(),
+            ca: "6p6xgHyF7AeE6TZkSmFsko444wqoP15icUSqi2jfGiPN".to_string(),
+            engagement: 42,
+            issued_at: 1_736_932_298,
+            expires_at: Some(1_737_537_098),
+            not_before: Some(1_736_935_898),
+            nonce: [5u8; NONCE_LEN],
+            campaign: Some("launch".to_string()),
+            policy_id: "legacy".to_string(),
+            key_id: "3a0e06ba".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let code = code();
+        let encoded = code.encode();
+        assert!(encoded.starts_with("v2-"));
+        assert_eq!(RedeemCodeV2::decode(&encoded).unwrap(), code);
+
+        // 可选字段为空时省略
+        let full_len = code.to_cbor().len();
+        let minimal = RedeemCodeV2 {
+            expires_at: None,
+            not_before: None,
+            campaign: None,
+            ..code
+        };
+        assert!(minimal.to_cbor().len() < full_len);
+        assert_eq!(minimal.to_cbor()[0], 0xa8);
+        assert_eq!(RedeemCodeV2::decode(&minimal.encode()).unwrap(), minimal);
+    }
+
+    #[test]
+    fn test_deterministic_encoding() {
+        let cbor = code().to_cbor();
+        assert_eq!(cbor, code().to_cbor());
+        // map 头部之后第一个 key 是 1 (client)
+        assert_eq!(cbor[0], 0xab);
+        assert_eq!(cbor[1], 0x01);
+    }
+
+    #[test]
+    fn test_checksum() {
+        let encoded = code().encode();
+        let last = encoded.chars().last().unwrap();
+        let replacement = if last == '2' { '3' } else { '2' };
+        let tampered = format!("{}{}", &encoded[..encoded.len() - 1], replacement);
+
+        assert!(matches!(
+            RedeemCodeV2::decode(&tampered),
+            Err(ApiError::InvalidRedeemCode(_))
+        ));
+        assert!(RedeemCodeV2::decode("v1-twitter-1-ca-1").is_err());
+    }
+
+    #[test]
+    fn test_rejects_non_canonical_cbor() {
+        let Value::Map(mut entries) = ciborium::from_reader(code().to_cbor().as_slice()).unwrap()
+        else {
+            unreachable!()
+        };
+
+        // key 顺序错误
+        entries.swap(0, 1);
+        let mut unordered = Vec::new();
+        ciborium::into_writer(&Value::Map(entries), &mut unordered).unwrap();
+        assert!(RedeemCodeV2::from_cbor(&unordered).is_err());
+
+        // engagement 使用非最短的整数编码
+        let mut cbor = code().to_cbor();
+        let position = cbor
+            .windows(2)
+            .position(|window| window == [0x04, 0x18])
+            .unwrap();
+        cbor.splice(position + 1..position + 3, [0x19, 0x00, 0x2a]);
+        assert!(matches!(
+            RedeemCodeV2::from_cbor(&cbor),
+            Err(ApiError::InvalidRedeemCode(_))
+        ));
+    }
+
+    #[test]
+    fn test_ignores_unknown_fields() {
+        let Value::Map(mut entries) = ciborium::from_reader(code().to_cbor().as_slice()).unwrap()
+        else {
+            unreachable!()
+        };
+        entries.push((Value::Integer(99.into()), Value::Text("future".to_string())));
+        let mut cbor = Vec::new();
+        ciborium::into_writer(&Value::Map(entries), &mut cbor).unwrap();
+
+        assert_eq!(RedeemCodeV2::from_cbor(&cbor).unwrap(), code());
+    }
+}
@@ -0,0 +1,395 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use url::Url;
+
+use crate::error::ApiError;
+use crate::utils::decode_app_data::DecodedData;
+use crate::utils::http_transcript::HttpRequest;
+
+/// GraphQL 请求的路径前缀: `/i/api/graphql/<query id>/<operation>`
+const GRAPHQL_PATH_PREFIX: &str = "/i/api/graphql/";
+
+/// notary 签名的请求必须命中的目标, 通过配置文件的 `[request_target]` 修改
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RequestTargetPolicy {
+    /// 允许的 host, 比较时忽略 `www.`
+    pub allowed_hosts: Vec<String>,
+    /// post session 允许的 GraphQL operation
+    pub post_operations: Vec<String>,
+    /// author session 允许的 GraphQL operation, 必须能证明当前登录的用户
+    pub author_operations: Vec<String>,
+    /// author session 允许的 REST 路径
+    pub author_paths: Vec<String>,
+    /// 允许的响应状态码
+    pub allowed_statuses: Vec<u16>,
+}
+
+impl Default for RequestTargetPolicy {
+    fn default() -> Self {
+        RequestTargetPolicy {
+            allowed_hosts: vec!["x.com".to_string(), "twitter.com".to_string()],
+            post_operations: vec!["TweetDetail".to_string(), "TweetResultByRestId".to_string()],
+            author_operations: vec!["Viewer".to_string()],
+            author_paths: vec!["/i/api/1.1/account/verify_credentials.json".to_string()],
+            allowed_statuses: vec![200],
+        }
+    }
+}
+
+/// 从请求中解析出的目标
+struct RequestTarget {
+    url: Url,
+    host: String,
+}
+
+impl RequestTargetPolicy {
+    /// 任何一项为空都会拒绝所有 session
+    pub fn validate(&self) -> Result<(), ApiError> {
+        let required = [
+            ("allowed_hosts", self.allowed_hosts.is_empty()),
+            ("post_operations", self.post_operations.is_empty()),
+            (
+                "author_operations or author_paths",
+                self.author_operations.is_empty() && self.author_paths.is_empty(),
+            ),
+            ("allowed_statuses", self.allowed_statuses.is_empty()),
+        ];
+        for (name, is_empty) in required {
+            if is_empty {
+                return Err(ApiError::ConfigError(format!(
+                    "request_target.{} must not be empty",
+                    name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// 校验 post session 来自 TweetDetail / TweetResultByRestId, 返回 variables 中的推文 ID
+    pub fn check_post_session(&self, decoded_data: &DecodedData) -> Result<String, ApiError> {
+        let request = &decoded_data.transcript.requests[0];
+        let target = self.check_exchange(decoded_data)?;
+
+        let operation = graphql_operation(&target.url).ok_or_else(|| {
+            ApiError::InvalidRequestTarget("Post session is not a GraphQL request".to_string())
+        })?;
+        if !self
+            .post_operations
+            .iter()
+            .any(|allowed| allowed == operation)
+        {
+            return Err(ApiError::InvalidRequestTarget(format!(
+                "Post session operation {} is not allowed",
+                operation
+            )));
+        }
+
+        let variables = graphql_variables(&target.url, request)?;
+        ["focalTweetId", "tweetId"]
+            .iter()
+            .find_map(|key| variables.get(key).and_then(Value::as_str))
+            .map(|tweet_id| tweet_id.to_string())
+            .ok_or_else(|| {
+                ApiError::InvalidRequestTarget(
+                    "Post session does not declare the focal tweet ID".to_string(),
+                )
+            })
+    }
+
+    /// 校验 author session 来自能证明登录用户身份的接口
+    pub fn check_author_session(&self, decoded_data: &DecodedData) -> Result<(), ApiError> {
+        let target = self.check_exchange(decoded_data)?;
+
+        let allowed = match graphql_operation(&target.url) {
+            Some(operation) => self
+                .author_operations
+                .iter()
+                .any(|allowed| allowed == operation),
+            None => self
+                .author_paths
+                .iter()
+                .any(|allowed| allowed == target.url.path()),
+        };
+
+        if !allowed {
+            return Err(ApiError::InvalidRequestTarget(format!(
+                "Author session endpoint {} does not prove the logged-in user",
+                target.url.path()
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// 校验 host 以及响应状态
+    fn check_exchange(&self, decoded_data: &DecodedData) -> Result<RequestTarget, ApiError> {
+        let request = &decoded_data.transcript.requests[0];
+        let response = &decoded_data.transcript.responses[0];
+        let target = request_target(request)?;
+
+        let host = target.host.trim_start_matches("www.");
+        if !self
+            .allowed_hosts
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(host))
+        {
+            return Err(ApiError::InvalidRequestTarget(format!(
+                "Host {} is not allowed",
+                target.host
+            )));
+        }
+
+        if !self.allowed_statuses.contains(&response.status) {
+            return Err(ApiError::InvalidRequestTarget(format!(
+                "Notarized response has status {}",
+                response.status
+            )));
+        }
+
+        Ok(target)
+    }
+}
+
+fn request_target(request: &HttpRequest) -> Result<RequestTarget, ApiError> {
+    let invalid_target =
+        || ApiError::InvalidRequestTarget(format!("Invalid request target {}", request.target));
+
+    // 必须有且只有一个 Host header, absolute-form 的 authority 也要与之一致,
+    // 否则签名中的 host 与实际请求的服务器可能不是同一个
+    let host = request.host().ok_or_else(|| {
+        ApiError::InvalidRequestTarget(
+            "Request must have exactly one Host header matching its target".to_string(),
+        )
+    })?;
+    // origin-form 的请求需要结合 Host header
+    let url = if request.target.starts_with('/') {
+        Url::parse(&format!("https://{}{}", host, request.target))
+    } else {
+        Url::parse(&request.target)
+    }
+    .map_err(|_| invalid_target())?;
+
+    let host = url
+        .host_str()
+        .ok_or_else(invalid_target)?
+        .to_ascii_lowercase();
+
+    Ok(RequestTarget { url, host })
+}
+
+/// `/i/api/graphql/<query id>/<operation>` 中的 operation
+fn graphql_operation(url: &Url) -> Option<&str> {
+    let mut segments = url.path().strip_prefix(GRAPHQL_PATH_PREFIX)?.split('/');
+    let _query_id = segments.next().filter(|id| !id.is_empty())?;
+    let operation = segments.next().filter(|op| !op.is_empty())?;
+    segments.next().is_none().then_some(operation)
+}
+
+/// GET 请求的 variables 在 query 中, POST 请求的在 JSON body 中
+fn graphql_variables(url: &Url, request: &HttpRequest) -> Result<Value, ApiError> {
+    let invalid_variables =
+        || ApiError::InvalidRequestTarget("GraphQL variables are not valid JSON".to_string());
+
+    if let Some((_, variables)) = url.query_pairs().find(|(key, _)| key == "variables") {
+        return serde_json::from_str(&variables).map_err(|_| invalid_variables());
+    }
+
+    let body: Value = serde_json::from_slice(&request.body).map_err(|_| invalid_variables())?;
+    body.get("variables").cloned().ok_or_else(invalid_variables)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::decode_app_data::decode_app_data;
+    use crate::utils::http_transcript::TranscriptLimits;
+
+    fn decoded(request_head: &str, request_body: &str, status: &str) -> DecodedData {
+        let transcript = format!(
+            "{}\r\ncontent-length: {}\r\n\r\n{}HTTP/1.1 {}\r\ncontent-length: 2\r\n\r\n{{}}",
+            request_head,
+            request_body.len(),
+            request_body,
+            status
+        );
+        decode_app_data(&hex::encode(transcript), &TranscriptLimits::default()).unwrap()
+    }
+
+    #[test]
+    fn test_check_post_session_fixture() {
+        let decoded_data = decode_app_data(
+            include_str!("../../utils/fixtures/tweet_detail.hex"),
+            &TranscriptLimits::default(),
+        )
+        .unwrap();
+        let policy = RequestTargetPolicy::default();
+
+        assert_eq!(
+            policy.check_post_session(&decoded_data).unwrap(),
+            "1879456397454385265"
+        );
+        // TweetDetail 不能作为 author session
+        assert!(matches!(
+            policy.check_author_session(&decoded_data),
+            Err(ApiError::InvalidRequestTarget(_))
+        ));
+    }
+
+    #[test]
+    fn test_check_post_session_variants() {
+        let policy = RequestTargetPolicy::default();
+
+        let by_rest_id = decoded(
+            "GET /i/api/graphql/abc/TweetResultByRestId?variables=%7B%22tweetId%22%3A%2242%22%7D HTTP/1.1\r\nhost: twitter.com",
+            "",
+            "200 OK",
+        );
+        assert_eq!(policy.check_post_session(&by_rest_id).unwrap(), "42");
+
+        let post_body = decoded(
+            "POST https://x.com/i/api/graphql/abc/TweetDetail HTTP/1.1\r\nhost: x.com",
+            r#"{"variables":{"focalTweetId":"43"}}"#,
+            "200 OK",
+        );
+        assert_eq!(policy.check_post_session(&post_body).unwrap(), "43");
+    }
+
+    #[test]
+    fn test_check_post_session_rejects_other_targets() {
+        let policy = RequestTargetPolicy::default();
+
+        let cases = [
+            // 非 Twitter 的 host
+            (
+                "GET https://evil.com/i/api/graphql/abc/TweetDetail?variables=%7B%22focalTweetId%22%3A%2242%22%7D HTTP/1.1\r\nhost: evil.com",
+                "200 OK",
+            ),
+            // absolute-form 与 Host header 不一致
+            (
+                "GET https://x.com/i/api/graphql/abc/TweetDetail?variables=%7B%22focalTweetId%22%3A%2242%22%7D HTTP/1.1\r\nhost: evil.com",
+                "200 OK",
+            ),
+            (
+                "GET https://evil.com/i/api/graphql/abc/TweetDetail?variables=%7B%22focalTweetId%22%3A%2242%22%7D HTTP/1.1\r\nhost: x.com",
+                "200 OK",
+            ),
+            // 缺少 Host header
+            (
+                "GET https://x.com/i/api/graphql/abc/TweetDetail?variables=%7B%22focalTweetId%22%3A%2242%22%7D HTTP/1.1\r\naccept: */*",
+                "200 OK",
+            ),
+            // 重复的 Host header
+            (
+                "GET /i/api/graphql/abc/TweetDetail?variables=%7B%22focalTweetId%22%3A%2242%22%7D HTTP/1.1\r\nhost: x.com\r\nhost: evil.com",
+                "200 OK",
+            ),
+            (
+                "GET /i/api/graphql/abc/TweetDetail?variables=%7B%22focalTweetId%22%3A%2242%22%7D HTTP/1.1\r\nhost: evil.com\r\nhost: x.com",
+                "200 OK",
+            ),
+            // 其他的 GraphQL operation
+            (
+                "GET /i/api/graphql/abc/UserTweets?variables=%7B%22focalTweetId%22%3A%2242%22%7D HTTP/1.1\r\nhost: x.com",
+                "200 OK",
+            ),
+            // 不是 GraphQL 请求
+            ("GET /home HTTP/1.1\r\nhost: x.com", "200 OK"),
+            // 缺少推文 ID
+            (
+                "GET /i/api/graphql/abc/TweetDetail?variables=%7B%7D HTTP/1.1\r\nhost: x.com",
+                "200 OK",
+            ),
+            // 请求失败
+            (
+                "GET /i/api/graphql/abc/TweetDetail?variables=%7B%22focalTweetId%22%3A%2242%22%7D HTTP/1.1\r\nhost: x.com",
+                "404 Not Found",
+            ),
+        ];
+
+        for (request_head, status) in cases {
+            assert!(
+                matches!(
+                    policy.check_post_session(&decoded(request_head, "", status)),
+                    Err(ApiError::InvalidRequestTarget(_))
+                ),
+                "{} should be rejected",
+                request_head
+            );
+        }
+    }
+
+    #[test]
+    fn test_check_author_session() {
+        let policy = RequestTargetPolicy::default();
+
+        let viewer = decoded(
+            "GET /i/api/graphql/abc/Viewer?variables=%7B%7D HTTP/1.1\r\nhost: x.com",
+            "",
+            "200 OK",
+        );
+        assert!(policy.check_author_session(&viewer).is_ok());
+
+        let credentials = decoded(
+            "GET https://www.x.com/i/api/1.1/account/verify_credentials.json HTTP/1.1\r\nhost: www.x.com",
+            "",
+            "200 OK",
+        );
+        assert!(policy.check_author_session(&credentials).is_ok());
+
+        let profile = decoded(
+            "GET /i/api/graphql/abc/UserByScreenName?variables=%7B%7D HTTP/1.1\r\nhost: x.com",
+            "",
+            "200 OK",
+        );
+        assert!(matches!(
+            policy.check_author_session(&profile),
+            Err(ApiError::InvalidRequestTarget(_))
+        ));
+    }
+
+    #[test]
+    fn test_configured_policy() {
+        let policy: RequestTargetPolicy = toml::from_str(
+            r#"
+            allowed_hosts = ["x.com"]
+            post_operations = ["TweetResultByRestId"]
+            allowed_statuses = [200, 203]
+            "#,
+        )
+        .unwrap();
+        assert!(policy.validate().is_ok());
+        // 未配置的项使用默认值
+        assert_eq!(policy.author_operations, ["Viewer"]);
+
+        let by_rest_id = "GET /i/api/graphql/abc/TweetResultByRestId?variables=%7B%22tweetId%22%3A%2242%22%7D HTTP/1.1\r\nhost: x.com";
+        let detail = "GET /i/api/graphql/abc/TweetDetail?variables=%7B%22focalTweetId%22%3A%2242%22%7D HTTP/1.1\r\nhost: x.com";
+        assert_eq!(
+            policy
+                .check_post_session(&decoded(
+                    by_rest_id,
+                    "",
+                    "203 Non-Authoritative Information"
+                ))
+                .unwrap(),
+            "42"
+        );
+        let rejected = [
+            (detail, "200 OK"),
+            (by_rest_id, "201 Created"),
+            (&by_rest_id.replace("x.com", "twitter.com"), "200 OK"),
+        ];
+        for (request_head, status) in rejected {
+            assert!(matches!(
+                policy.check_post_session(&decoded(request_head, "", status)),
+                Err(ApiError::InvalidRequestTarget(_))
+            ));
+        }
+
+        let empty = RequestTargetPolicy {
+            allowed_statuses: vec![],
+            ..RequestTargetPolicy::default()
+        };
+        assert!(matches!(empty.validate(), Err(ApiError::ConfigError(_))));
+    }
+}
@@ -0,0 +1,588 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::ApiError;
+
+use super::tweet_claims::TweetClaims;
+
+/// 兑换码中 engagement 的计算方式, 按推文中的 CA 选择 campaign, 再由 campaign 选择 policy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScoringConfig {
+    /// 没有 campaign 列出推文中的 CA 时使用的 campaign, 不设置时拒绝这样的推文
+    #[serde(default)]
+    pub default_campaign: Option<String>,
+    pub policies: Vec<ScoringPolicy>,
+    #[serde(default)]
+    pub campaigns: Vec<Campaign>,
+    /// campaign 没有设置 `validity_secs` 时兑换码的有效时长 (秒), 都不设置时永不过期
+    #[serde(default)]
+    pub default_validity_secs: Option<u64>,
+}
+
+/// campaign 与 policy 的对应关系, 以及该 campaign 兑换码的有效期
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Campaign {
+    pub id: String,
+    pub policy: String,
+    /// 属于该 campaign 的 CA, 一个 CA 只能属于一个 campaign
+    #[serde(default)]
+    pub cas: Vec<String>,
+    /// 兑换码生效 (签发时间与 `not_before` 中较晚的一个) 后的有效时长 (秒)
+    #[serde(default)]
+    pub validity_secs: Option<u64>,
+    /// 兑换码在该时间 (unix 秒) 之前不能兑换
+    #[serde(default)]
+    pub not_before: Option<u64>,
+    /// 只接受在该时间范围内发布的推文
+    #[serde(flatten)]
+    pub tweet_window: TweetWindow,
+}
+
+/// 推文发布时间的要求, 时间均为 unix 秒, 未设置的条件不检查
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TweetWindow {
+    /// 推文不能早于 campaign 开始时间
+    #[serde(default)]
+    pub starts_at: Option<u64>,
+    /// 推文不能晚于 campaign 结束时间
+    #[serde(default)]
+    pub ends_at: Option<u64>,
+    /// 推文发布后多久之内可以领取兑换码
+    #[serde(default)]
+    pub max_tweet_age_secs: Option<u64>,
+}
+
+impl TweetWindow {
+    pub fn check(&self, created_at: u64, now: u64) -> Result<(), ApiError> {
+        let outside = |reason: String| {
+            ApiError::TweetOutsideWindow(format!(
+                "Tweet posted at {} is outside the campaign window: {}",
+                created_at, reason
+            ))
+        };
+
+        if let Some(starts_at) = self.starts_at.filter(|&starts_at| created_at < starts_at) {
+            return Err(outside(format!("campaign starts at {}", starts_at)));
+        }
+        if let Some(ends_at) = self.ends_at.filter(|&ends_at| created_at > ends_at) {
+            return Err(outside(format!("campaign ended at {}", ends_at)));
+        }
+        if let Some(max_age) = self
+            .max_tweet_age_secs
+            .filter(|&max_age| now.saturating_sub(created_at) > max_age)
+        {
+            return Err(outside(format!(
+                "tweets older than {}s are not accepted",
+                max_age
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// 一个评分公式: `base + Σ weight * scale(clamp(metric))`, 结果向下取整
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScoringPolicy {
+    /// 写入签名结果, 下游据此知道分数是如何计算的
+    pub id: String,
+    #[serde(default)]
+    pub base: f64,
+    pub terms: Vec<ScoringTerm>,
+    /// 低于该分数的推文不签发兑换码
+    #[serde(default)]
+    pub min_score: Option<u64>,
+    /// 分数上限
+    #[serde(default)]
+    pub max_score: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScoringTerm {
+    pub metric: EngagementMetric,
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+    #[serde(default)]
+    pub scale: Scale,
+    /// 互动数低于该值时这一项不计分
+    #[serde(default)]
+    pub min: Option<u64>,
+    /// 互动数超过该值时按该值计算
+    #[serde(default)]
+    pub cap: Option<u64>,
+}
+
+/// 配置中使用与 TweetDetail legacy 字段相同的名称
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EngagementMetric {
+    #[serde(rename = "bookmark_count")]
+    Bookmark,
+    #[serde(rename = "favorite_count")]
+    Favorite,
+    #[serde(rename = "retweet_count")]
+    Retweet,
+    #[serde(rename = "reply_count")]
+    Reply,
+    #[serde(rename = "quote_count")]
+    Quote,
+    #[serde(rename = "view_count")]
+    View,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scale {
+    #[default]
+    Linear,
+    /// `ln(1 + count)`, 降低头部推文的权重
+    Log,
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+impl EngagementMetric {
+    /// 没有浏览量的推文按 0 计算
+    fn count(self, claims: &TweetClaims) -> u64 {
+        match self {
+            EngagementMetric::Bookmark => claims.bookmark_count,
+            EngagementMetric::Favorite => claims.favorite_count,
+            EngagementMetric::Retweet => claims.retweet_count,
+            EngagementMetric::Reply => claims.reply_count,
+            EngagementMetric::Quote => claims.quote_count,
+            EngagementMetric::View => claims.view_count.unwrap_or(0),
+        }
+    }
+}
+
+impl ScoringTerm {
+    fn score(&self, claims: &TweetClaims) -> f64 {
+        let count = self.metric.count(claims);
+        if self.min.is_some_and(|min| count < min) {
+            return 0.0;
+        }
+        let count = self.cap.map_or(count, |cap| count.min(cap)) as f64;
+
+        let scaled = match self.scale {
+            Scale::Linear => count,
+            Scale::Log => count.ln_1p(),
+        };
+        self.weight * scaled
+    }
+}
+
+impl ScoringPolicy {
+    pub fn score(&self, claims: &TweetClaims) -> Result<u64, ApiError> {
+        let score = self
+            .terms
+            .iter()
+            .fold(self.base, |score, term| score + term.score(claims))
+            .floor();
+
+        // u64::MAX 转换为 f64 后会进位到 2^64, 因此用 >= 判断
+        if !score.is_finite() || score >= u64::MAX as f64 {
+            return Err(ApiError::InvalidMessage(
+                "Engagement count is too large".to_string(),
+            ));
+        }
+        let score = score.max(0.0) as u64;
+        let score = self.max_score.map_or(score, |max| score.min(max));
+
+        if let Some(min_score) = self.min_score {
+            if score < min_score {
+                return Err(ApiError::EngagementTooLow(format!(
+                    "Engagement score {} is below the minimum {} of policy {}",
+                    score, min_score, self.id
+                )));
+            }
+        }
+
+        Ok(score)
+    }
+
+    fn validate(&self) -> Result<(), ApiError> {
+        let invalid_policy =
+            |reason: &str| ApiError::ConfigError(format!("Scoring policy {} {}", self.id, reason));
+
+        if !self.base.is_finite() {
+            return Err(invalid_policy("has a base that is not finite"));
+        }
+        for term in &self.terms {
+            if !term.weight.is_finite() {
+                return Err(invalid_policy("has a weight that is not finite"));
+            }
+            if let (Some(min), Some(cap)) = (term.min, term.cap) {
+                if min > cap {
+                    return Err(invalid_policy("has a term whose min is above its cap"));
+                }
+            }
+        }
+        if let (Some(min), Some(max)) = (self.min_score, self.max_score) {
+            if min > max {
+                return Err(invalid_policy("has min_score above max_score"));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ScoringConfig {
+    /// 编译进程序的默认配置, 与原来的 `bookmark + favorite + retweet + 1` 一致
+    pub fn builtin() -> Result<Self, ApiError> {
+        Self::from_json(include_str!("../../scoring/scoring.json"))
+    }
+
+    /// 配置了 `scoring.config_path` 时从该文件读取, 否则使用内置配置
+    pub fn load(path: Option<&Path>) -> Result<Self, ApiError> {
+        match path {
+            Some(path) => {
+                let json = std::fs::read_to_string(path).map_err(|err| {
+                    ApiError::ConfigError(format!(
+                        "Cannot read scoring config {}: {}",
+                        path.display(),
+                        err
+                    ))
+                })?;
+                Self::from_json(&json)
+            }
+            None => Self::builtin(),
+        }
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, ApiError> {
+        let config: ScoringConfig = serde_json::from_str(json)
+            .map_err(|err| ApiError::ConfigError(format!("Invalid scoring config: {}", err)))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn campaign(&self, campaign: &str) -> Result<&Campaign, ApiError> {
+        self.campaigns
+            .iter()
+            .find(|known| known.id == campaign)
+            .ok_or_else(|| ApiError::NotFound(format!("Campaign {} is unknown", campaign)))
+    }
+
+    /// 推文中的 CA 所属的 campaign, campaign 由服务端决定, 客户端不能选择
+    pub fn campaign_for_ca(&self, ca: &str) -> Result<&Campaign, ApiError> {
+        let listed = self
+            .campaigns
+            .iter()
+            .find(|campaign| campaign.cas.iter().any(|known| same_ca(known, ca)));
+        match (listed, &self.default_campaign) {
+            (Some(campaign), _) => Ok(campaign),
+            (None, Some(default_campaign)) => self.campaign(default_campaign),
+            (None, None) => Err(ApiError::NotFound(format!("No campaign accepts CA {}", ca))),
+        }
+    }
+
+    pub fn policy(&self, campaign: &Campaign) -> Result<&ScoringPolicy, ApiError> {
+        self.find_policy(&campaign.policy).ok_or_else(|| {
+            ApiError::ConfigError(format!("Scoring policy {} is unknown", campaign.policy))
+        })
+    }
+
+    fn find_policy(&self, policy_id: &str) -> Option<&ScoringPolicy> {
+        self.policies.iter().find(|policy| policy.id == policy_id)
+    }
+
+    fn validate(&self) -> Result<(), ApiError> {
+        let mut policy_ids = HashSet::new();
+        for policy in &self.policies {
+            if !policy_ids.insert(policy.id.as_str()) {
+                return Err(ApiError::ConfigError(format!(
+                    "Scoring policy {} is defined more than once",
+                    policy.id
+                )));
+            }
+            policy.validate()?;
+        }
+
+        if self.default_validity_secs == Some(0) {
+            return Err(ApiError::ConfigError(
+                "default_validity_secs must be positive".to_string(),
+            ));
+        }
+
+        let mut campaign_ids = HashSet::new();
+        for campaign in &self.campaigns {
+            if !campaign_ids.insert(campaign.id.as_str()) {
+                return Err(ApiError::ConfigError(format!(
+                    "Campaign {} is defined more than once",
+                    campaign.id
+                )));
+            }
+            if campaign.validity_secs == Some(0) {
+                return Err(ApiError::ConfigError(format!(
+                    "Campaign {} validity_secs must be positive",
+                    campaign.id
+                )));
+            }
+            for ca in &campaign.cas {
+                if let Some(other) = self.campaigns.iter().find(|other| {
+                    other.id != campaign.id && other.cas.iter().any(|known| same_ca(known, ca))
+                }) {
+                    return Err(ApiError::ConfigError(format!(
+                        "CA {} is listed by both campaign {} and campaign {}",
+                        ca, campaign.id, other.id
+                    )));
+                }
+            }
+            let window = &campaign.tweet_window;
+            if window
+                .starts_at
+                .zip(window.ends_at)
+                .is_some_and(|(starts_at, ends_at)| starts_at > ends_at)
+            {
+                return Err(ApiError::ConfigError(format!(
+                    "Campaign {} starts_at must not be after ends_at",
+                    campaign.id
+                )));
+            }
+        }
+
+        if let Some(default_campaign) = &self.default_campaign {
+            self.campaign(default_campaign).map_err(|_| {
+                ApiError::ConfigError(format!(
+                    "default_campaign {} is not a configured campaign",
+                    default_campaign
+                ))
+            })?;
+        }
+
+        let referenced = self
+            .campaigns
+            .iter()
+            .map(|campaign| campaign.policy.as_str());
+        for policy_id in referenced {
+            if self.find_policy(policy_id).is_none() {
+                return Err(ApiError::ConfigError(format!(
+                    "Scoring policy {} is unknown",
+                    policy_id
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// EVM 地址不区分大小写, base58 地址区分
+fn same_ca(known: &str, ca: &str) -> bool {
+    if known.starts_with("0x") {
+        known.eq_ignore_ascii_case(ca)
+    } else {
+        known == ca
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CA: &str = "6p6xgHyF7AeE6TZkSmFsko444wqoP15icUSqi2jfGiPN";
+
+    fn claims() -> TweetClaims {
+        TweetClaims {
+            post_id: "42".to_string(),
+            author_id: "7".to_string(),
+            content: "ca:6p6xgHyF7AeE6TZkSmFsko444wqoP15icUSqi2jfGiPN".to_string(),
+            created_at: "Wed Jan 15 09:11:38 +0000 2025".to_string(),
+            bookmark_count: 2,
+            favorite_count: 30,
+            retweet_count: 4,
+            reply_count: 5,
+            quote_count: 6,
+            view_count: Some(1_000),
+        }
+    }
+
+    #[test]
+    fn test_builtin_policy_matches_legacy_formula() {
+        let config = ScoringConfig::builtin().unwrap();
+        let campaign = config.campaign_for_ca(CA).unwrap();
+        let policy = config.policy(campaign).unwrap();
+
+        assert_eq!(campaign.id, "default");
+        assert_eq!(policy.id, "legacy");
+        assert_eq!(policy.score(&claims()).unwrap(), 2 + 30 + 4 + 1);
+        assert!(matches!(
+            config.campaign("unknown"),
+            Err(ApiError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_campaign_for_ca() {
+        let evm_ca = "0x1f9840a85d5af5bf1d1762f925bdaddc4201f984";
+        let campaigns = r#"[
+            { "id": "launch", "policy": "legacy", "cas": ["6p6xgHyF7AeE6TZkSmFsko444wqoP15icUSqi2jfGiPN"] },
+            { "id": "evm", "policy": "legacy", "cas": ["0x1F9840a85d5aF5bf1D1762F925BDADdC4201F984"] },
+            { "id": "other", "policy": "legacy" }
+        ]"#;
+        let config = |default_campaign: &str| {
+            ScoringConfig::from_json(&format!(
+                r#"{{ {} "policies": [{{ "id": "legacy", "terms": [] }}], "campaigns": {} }}"#,
+                default_campaign, campaigns
+            ))
+            .unwrap()
+        };
+
+        let with_default = config(r#""default_campaign": "other","#);
+        assert_eq!(with_default.campaign_for_ca(CA).unwrap().id, "launch");
+        // EVM 地址不区分大小写
+        assert_eq!(with_default.campaign_for_ca(evm_ca).unwrap().id, "evm");
+        assert_eq!(
+            with_default
+                .campaign_for_ca("7p6xgHyF7AeE6TZkSmFsko444wqoP15icUSqi2jfGiPN")
+                .unwrap()
+                .id,
+            "other"
+        );
+
+        // 没有默认 campaign 时拒绝未列出的 CA, 而不是不做任何限制
+        let without_default = config("");
+        assert_eq!(without_default.campaign_for_ca(CA).unwrap().id, "launch");
+        assert!(matches!(
+            without_default.campaign_for_ca("7p6xgHyF7AeE6TZkSmFsko444wqoP15icUSqi2jfGiPN"),
+            Err(ApiError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_weighted_policy() {
+        let config = ScoringConfig::from_json(
+            r#"{
+                "policies": [
+                    { "id": "legacy", "terms": [] },
+                    {
+                        "id": "weighted",
+                        "terms": [
+                            { "metric": "favorite_count", "weight": 2, "cap": 10 },
+                            { "metric": "reply_count", "weight": 3, "min": 6 },
+                            { "metric": "quote_count", "weight": 0.5 },
+                            { "metric": "view_count", "scale": "log", "weight": 10 }
+                        ],
+                        "max_score": 80
+                    }
+                ],
+                "campaigns": [{ "id": "launch", "policy": "weighted" }]
+            }"#,
+        )
+        .unwrap();
+        let policy = config.policy(config.campaign("launch").unwrap()).unwrap();
+        assert_eq!(policy.id, "weighted");
+
+        // 2 * 10 + 0 + 0.5 * 6 + 10 * ln(1001) ≈ 92.1, 再被 max_score 截断
+        assert_eq!(policy.score(&claims()).unwrap(), 80);
+
+        let no_views = TweetClaims {
+            view_count: None,
+            ..claims()
+        };
+        assert_eq!(policy.score(&no_views).unwrap(), 23);
+    }
+
+    #[test]
+    fn test_min_score() {
+        let config = ScoringConfig::from_json(
+            r#"{
+                "policies": [{
+                    "id": "strict",
+                    "terms": [{ "metric": "retweet_count" }],
+                    "min_score": 5
+                }],
+                "campaigns": [{ "id": "strict", "policy": "strict" }]
+            }"#,
+        )
+        .unwrap();
+        let policy = config.policy(config.campaign("strict").unwrap()).unwrap();
+
+        let err = policy.score(&claims()).unwrap_err();
+        assert!(matches!(err, ApiError::EngagementTooLow(_)));
+        // 与格式错误的请求区分开
+        assert_eq!(err.code(), crate::error::ErrorCode::EngagementTooLow);
+        assert_eq!(err.status(), axum::http::StatusCode::UNPROCESSABLE_ENTITY);
+        let popular = TweetClaims {
+            retweet_count: 5,
+            ..claims()
+        };
+        assert_eq!(policy.score(&popular).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_tweet_window() {
+        let config = ScoringConfig::from_json(
+            r#"{
+                "policies": [{ "id": "legacy", "terms": [] }],
+                "campaigns": [
+                    { "id": "launch", "policy": "legacy", "starts_at": 1000, "ends_at": 2000 },
+                    { "id": "fresh", "policy": "legacy", "max_tweet_age_secs": 3600 }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let launch = &config.campaign("launch").unwrap().tweet_window;
+        assert!(launch.check(1000, 5000).is_ok());
+        assert!(launch.check(2000, 5000).is_ok());
+        for created_at in [999, 2001] {
+            assert!(matches!(
+                launch.check(created_at, 5000),
+                Err(ApiError::TweetOutsideWindow(_))
+            ));
+        }
+
+        let fresh = &config.campaign("fresh").unwrap().tweet_window;
+        assert!(fresh.check(1000, 4600).is_ok());
+        assert!(matches!(
+            fresh.check(1000, 4601),
+            Err(ApiError::TweetOutsideWindow(_))
+        ));
+
+        assert!(TweetWindow::default().check(0, u64::MAX).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let cases = [
+            // 默认 campaign 不存在
+            r#"{ "default_campaign": "missing", "policies": [] }"#,
+            // campaign 引用了不存在的 policy
+            r#"{ "policies": [{ "id": "a", "terms": [] }],
+                 "campaigns": [{ "id": "c", "policy": "b" }] }"#,
+            // 重复的 policy
+            r#"{ "policies": [{ "id": "a", "terms": [] }, { "id": "a", "terms": [] }] }"#,
+            // 未知的 metric
+            r#"{ "policies": [{ "id": "a", "terms": [{ "metric": "likes" }] }] }"#,
+            // min 大于 cap
+            r#"{ "policies": [{ "id": "a", "terms": [{ "metric": "view_count", "min": 5, "cap": 1 }] }] }"#,
+            // 有效期为 0
+            r#"{ "policies": [{ "id": "a", "terms": [] }],
+                 "campaigns": [{ "id": "c", "policy": "a", "validity_secs": 0 }] }"#,
+            r#"{ "policies": [{ "id": "a", "terms": [] }],
+                 "default_validity_secs": 0 }"#,
+            // 同一个 CA 属于两个 campaign
+            r#"{ "policies": [{ "id": "a", "terms": [] }],
+                 "campaigns": [{ "id": "c", "policy": "a", "cas": ["0xAB"] }, { "id": "d", "policy": "a", "cas": ["0xab"] }] }"#,
+            // campaign 开始时间晚于结束时间
+            r#"{ "policies": [{ "id": "a", "terms": [] }],
+                 "campaigns": [{ "id": "c", "policy": "a", "starts_at": 2, "ends_at": 1 }] }"#,
+        ];
+
+        for json in cases {
+            assert!(
+                matches!(
+                    ScoringConfig::from_json(json),
+                    Err(ApiError::ConfigError(_))
+                ),
+                "{} should be rejected",
+                json
+            );
+        }
+    }
+}
@@ -0,0 +1,382 @@
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::ApiError;
+use crate::utils::*;
+
+/// Twitter `created_at` 的格式, 例如 `Wed Jan 15 09:11:38 +0000 2025`
+const CREATED_AT_FORMAT: &str = "%a %b %d %H:%M:%S %z %Y";
+
+/// 从 notary 签名的 TweetDetail 响应中解析出的推文信息, 是签发兑换码的唯一依据
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TweetClaims {
+    /// 推文的 rest_id
+    pub post_id: String,
+    /// 作者的 rest_id
+    pub author_id: String,
+    /// 推文全文, 长推文取 note_tweet 的内容
+    pub content: String,
+    /// Twitter 格式的发布时间, 例如 `Wed Jan 15 09:11:38 +0000 2025`
+    pub created_at: String,
+    pub bookmark_count: u64,
+    pub favorite_count: u64,
+    pub retweet_count: u64,
+    pub reply_count: u64,
+    pub quote_count: u64,
+    /// 浏览量, 部分推文没有
+    pub view_count: Option<u64>,
+}
+
+impl TweetClaims {
+    /// 在响应中找到 `focal_tweet_id` 对应的推文并解析
+    pub fn from_response(response_body: &str, focal_tweet_id: &str) -> Result<Self, ApiError> {
+        let response: Value = serde_json::from_str(response_body).map_err(|_| {
+            ApiError::InvalidTranscript("Notarized response body is not valid JSON".to_string())
+        })?;
+
+        let tweet = find_tweet(&response, focal_tweet_id).ok_or_else(|| {
+            ApiError::NotFound("Focal tweet is missing from the notarized response".to_string())
+        })?;
+        let legacy = &tweet["legacy"];
+
+        let author_id = tweet
+            .pointer("/core/user_results/result/rest_id")
+            .or_else(|| legacy.get("user_id_str"))
+            .and_then(Value::as_str)
+            .ok_or_else(|| ApiError::NotFound("Author information is missing".to_string()))?;
+
+        let content = tweet
+            .pointer("/note_tweet/note_tweet_results/result/text")
+            .or_else(|| legacy.get("full_text"))
+            .and_then(Value::as_str)
+            .ok_or_else(|| ApiError::NotFound("Message content is missing".to_string()))?;
+
+        let created_at = legacy
+            .get("created_at")
+            .and_then(Value::as_str)
+            .ok_or_else(|| ApiError::NotFound("Creation time is missing".to_string()))?;
+
+        let count = |name: &str| {
+            legacy
+                .get(name)
+                .ok_or_else(|| ApiError::NotFound(format!("{} is missing", name)))
+                .and_then(engagement_count_from_json)
+        };
+
+        let view_count = tweet
+            .pointer("/views/count")
+            .map(engagement_count_from_json)
+            .transpose()?;
+
+        Ok(TweetClaims {
+            post_id: focal_tweet_id.to_string(),
+            author_id: author_id.to_string(),
+            content: content.to_string(),
+            created_at: created_at.to_string(),
+            bookmark_count: count("bookmark_count")?,
+            favorite_count: count("favorite_count")?,
+            retweet_count: count("retweet_count")?,
+            reply_count: count("reply_count")?,
+            quote_count: count("quote_count")?,
+            view_count,
+        })
+    }
+
+    /// 推文发布时间 (unix 秒)
+    pub fn created_at_unix(&self) -> Result<u64, ApiError> {
+        DateTime::parse_from_str(&self.created_at, CREATED_AT_FORMAT)
+            .ok()
+            .and_then(|created_at| u64::try_from(created_at.timestamp()).ok())
+            .ok_or_else(|| {
+                ApiError::InvalidMessage(format!(
+                    "Tweet creation time {} is not a valid Twitter date",
+                    self.created_at
+                ))
+            })
+    }
+
+    /// 可选的交叉校验: 客户端提供的 attribute 必须与解析出的推文一致, 缺少的 attribute 跳过
+    pub fn check_attributes(&self, attributes: &[Attribute]) -> Result<(), ApiError> {
+        let string_checks: [(&str, FindString, &str); 4] = [
+            ("id", find_post_id_attribute, &self.post_id),
+            ("author", find_author_attribute, &self.author_id),
+            ("content", find_content_attribute, &self.content),
+            ("created_at", find_created_at_attribute, &self.created_at),
+        ];
+        for (key, find_attribute, claim) in string_checks {
+            if let Some(value) = optional(find_attribute(attributes))? {
+                if value != claim {
+                    return Err(attribute_mismatch(key));
+                }
+            }
+        }
+
+        let count_checks: [(&str, FindCount, u64); 5] = [
+            (
+                "bookmark_count",
+                find_bookmark_count_attribute,
+                self.bookmark_count,
+            ),
+            (
+                "favorite_count",
+                find_favorite_count_attribute,
+                self.favorite_count,
+            ),
+            (
+                "retweet_count",
+                find_retweet_count_attribute,
+                self.retweet_count,
+            ),
+            ("reply_count", find_reply_count_attribute, self.reply_count),
+            ("quote_count", find_quote_count_attribute, self.quote_count),
+        ];
+        for (key, find_attribute, claim) in count_checks {
+            if let Some(value) = optional(find_attribute(attributes))? {
+                if value != claim {
+                    return Err(attribute_mismatch(key));
+                }
+            }
+        }
+
+        // 响应中没有浏览量时, 提供的 view_count 也算不一致
+        if let Some(value) = optional(find_view_count_attribute(attributes))? {
+            if Some(value) != self.view_count {
+                return Err(attribute_mismatch("view_count"));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+type FindString = fn(&[Attribute]) -> Result<String, ApiError>;
+type FindCount = fn(&[Attribute]) -> Result<u64, ApiError>;
+
+/// 缺少的 attribute 不参与校验, 其他错误 (格式错误、重复的 key) 直接返回
+fn optional<T>(result: Result<T, ApiError>) -> Result<Option<T>, ApiError> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(ApiError::NotFound(_)) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+fn attribute_mismatch(key: &str) -> ApiError {
+    ApiError::TranscriptMismatch(format!(
+        "Attribute {} does not match the notarized tweet",
+        key
+    ))
+}
+
+/// 从 author session 的响应中解析当前登录用户的 rest_id
+///
+/// 支持 GraphQL `Viewer` 与 `account/verify_credentials.json` 两种响应
+pub fn viewer_id_from_response(response_body: &str) -> Result<String, ApiError> {
+    let response: Value = serde_json::from_str(response_body).map_err(|_| {
+        ApiError::InvalidTranscript("Notarized response body is not valid JSON".to_string())
+    })?;
+
+    response
+        .pointer("/data/viewer/user_results/result/rest_id")
+        .or_else(|| response.get("id_str"))
+        .and_then(Value::as_str)
+        .map(|viewer_id| viewer_id.to_string())
+        .ok_or_else(|| ApiError::NotFound("Logged-in user is missing".to_string()))
+}
+
+/// 递归查找 rest_id 为 `tweet_id` 且带有 legacy 字段的推文对象
+///
+/// TweetWithVisibilityResults 会把推文包在 `tweet` 字段中, 递归查找时会自然展开
+fn find_tweet<'a>(value: &'a Value, tweet_id: &str) -> Option<&'a Value> {
+    match value {
+        Value::Object(map) => {
+            let is_tweet = map.get("rest_id").and_then(Value::as_str) == Some(tweet_id)
+                && map.get("legacy").is_some_and(Value::is_object);
+            if is_tweet {
+                return Some(value);
+            }
+            map.values().find_map(|child| find_tweet(child, tweet_id))
+        }
+        Value::Array(items) => items.iter().find_map(|child| find_tweet(child, tweet_id)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::decode_app_data::decode_app_data;
+    use crate::utils::http_transcript::TranscriptLimits;
+
+    fn fixture_claims() -> TweetClaims {
+        let decoded_data = decode_app_data(
+            include_str!("../../utils/fixtures/tweet_detail.hex"),
+            &TranscriptLimits::default(),
+        )
+        .unwrap();
+        TweetClaims::from_response(&decoded_data.response_body, "1879456397454385265").unwrap()
+    }
+
+    fn attribute(attribute_name: &str) -> Attribute {
+        Attribute {
+            attribute_hex: hex::encode(attribute_name),
+            attribute_name: attribute_name.to_string(),
+            signature: "test".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_tweet_claims_from_fixture() {
+        let claims = fixture_claims();
+
+        assert_eq!(claims.post_id, "1879456397454385265");
+        assert_eq!(claims.author_id, "1248668065148973061");
+        assert!(claims.content.starts_with("Dobby thinks friends"));
+        assert!(claims.content.contains("knocking! \n\nDobby"));
+        assert_eq!(claims.created_at, "Wed Jan 15 09:11:38 +0000 2025");
+        assert_eq!(claims.bookmark_count, 0);
+        assert_eq!(claims.favorite_count, 0);
+        assert_eq!(claims.retweet_count, 0);
+        assert_eq!(claims.reply_count, 0);
+        assert_eq!(claims.quote_count, 0);
+        assert_eq!(claims.view_count, Some(66));
+    }
+
+    #[test]
+    fn test_tweet_claims_note_tweet_with_visibility() {
+        let body = serde_json::json!({
+            "data": { "tweetResult": { "result": {
+                "__typename": "TweetWithVisibilityResults",
+                "tweet": {
+                    "rest_id": "42",
+                    "core": { "user_results": { "result": { "rest_id": "7" } } },
+                    "note_tweet": { "note_tweet_results": { "result": { "text": "a long note ca:xyz" } } },
+                    "legacy": {
+                        "full_text": "a long no…",
+                        "created_at": "Wed Jan 15 09:11:38 +0000 2025",
+                        "bookmark_count": 1, "favorite_count": 2, "retweet_count": 3,
+                        "reply_count": 4, "quote_count": 5
+                    }
+                }
+            } } }
+        })
+        .to_string();
+
+        let claims = TweetClaims::from_response(&body, "42").unwrap();
+        assert_eq!(claims.author_id, "7");
+        assert_eq!(claims.content, "a long note ca:xyz");
+        assert_eq!(claims.quote_count, 5);
+        assert_eq!(claims.view_count, None);
+
+        assert!(matches!(
+            TweetClaims::from_response(&body, "43"),
+            Err(ApiError::NotFound(_))
+        ));
+
+        // 互动数格式错误时返回错误而不是 panic
+        let invalid = body.replace("\"bookmark_count\":1", "\"bookmark_count\":-1");
+        assert!(matches!(
+            TweetClaims::from_response(&invalid, "42"),
+            Err(ApiError::InvalidMessage(_))
+        ));
+    }
+
+    #[test]
+    fn test_check_attributes() {
+        let claims = fixture_claims();
+
+        let attributes = vec![
+            attribute("id: \"1879456397454385265\""),
+            attribute("author: \"1248668065148973061\""),
+            attribute("content: \"Dobby thinks friends should always carry a sock in their pocket—you never know when freedom might come knocking! \\n\\nDobby once used a teacup to solve a big problem, proving even the smallest things can hold great power.\""),
+            attribute("created_at: \"Wed Jan 15 09:11:38 +0000 2025\""),
+            attribute("bookmark_count: 0"),
+            attribute("reply_count: 0"),
+            attribute("quote_count: 0"),
+            attribute("view_count: 66"),
+        ];
+        assert!(claims.check_attributes(&attributes).is_ok());
+        assert!(claims.check_attributes(&[]).is_ok());
+
+        // 值出现在响应的其他位置 (例如引用的推文、作者的 rest_id) 也不能通过
+        let injected = [
+            "id: \"1248668065148973061\"",
+            "author: \"1879456397454385265\"",
+            "favorite_count: 10",
+            "reply_count: 10",
+            "quote_count: 10",
+            "view_count: 1000",
+            "created_at: \"Thu Jan 16 09:11:38 +0000 2025\"",
+            "content: \"Dobby thinks ca:6p6xgHyF7AeE6TZkSmFsko444wqoP15icUSqi2jfGiPN\"",
+        ];
+        for attribute_name in injected {
+            assert!(
+                matches!(
+                    claims.check_attributes(&[attribute(attribute_name)]),
+                    Err(ApiError::TranscriptMismatch(_))
+                ),
+                "{} should not pass the cross check",
+                attribute_name
+            );
+        }
+        let without_views = TweetClaims {
+            view_count: None,
+            ..claims.clone()
+        };
+        assert!(matches!(
+            without_views.check_attributes(&[attribute("view_count: 0")]),
+            Err(ApiError::TranscriptMismatch(_))
+        ));
+        assert!(matches!(
+            claims.check_attributes(&[attribute("id: \"1\""), attribute("id: \"2\"")]),
+            Err(ApiError::InvalidMessage(_))
+        ));
+    }
+
+    #[test]
+    fn test_viewer_id_from_response() {
+        let viewer =
+            r#"{"data":{"viewer":{"user_results":{"result":{"rest_id":"1248668065148973061"}}}}}"#;
+        assert_eq!(
+            viewer_id_from_response(viewer).unwrap(),
+            "1248668065148973061"
+        );
+
+        let credentials = r#"{"id":1248668065148973061,"id_str":"1248668065148973061"}"#;
+        assert_eq!(
+            viewer_id_from_response(credentials).unwrap(),
+            "1248668065148973061"
+        );
+
+        assert!(matches!(
+            viewer_id_from_response("{}"),
+            Err(ApiError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_created_at_unix() {
+        let claims = fixture_claims();
+        assert_eq!(claims.created_at_unix().unwrap(), 1_736_932_298);
+
+        // 非 UTC 的时区也能正确换算
+        let offset = TweetClaims {
+            created_at: "Wed Jan 15 17:11:38 +0800 2025".to_string(),
+            ..fixture_claims()
+        };
+        assert_eq!(offset.created_at_unix().unwrap(), 1_736_932_298);
+
+        for created_at in ["2025-01-15T09:11:38Z", "Wed Jan 15 09:11:38 2025", ""] {
+            let invalid = TweetClaims {
+                created_at: created_at.to_string(),
+                ..fixture_claims()
+            };
+            assert!(
+                matches!(invalid.created_at_unix(), Err(ApiError::InvalidMessage(_))),
+                "{} should be rejected",
+                created_at
+            );
+        }
+    }
+}
@@ -1,18 +1,38 @@
+use ed25519_dalek::SigningKey;
+use tracing::{info, info_span};
+
 use crate::error::ApiError;
+use crate::metrics::{Metrics, Stage};
+use crate::utils::clock::Clock;
+use crate::utils::decode_app_data::*;
 use crate::utils::deserialize_message::*;
-use crate::utils::find_spec_attribute::*;
+use crate::utils::extract_ca::extract_ca;
+use crate::utils::http_transcript::TranscriptLimits;
+use crate::utils::notary_trust_store::*;
+use crate::utils::redeem_store::*;
 use crate::utils::verify_signature::*;
 
 use super::generate_redeemcode_and_sign::*;
+use super::request_target::*;
+use super::scoring_policy::*;
+use super::tweet_claims::*;
 
+#[allow(clippy::too_many_arguments)]
 pub fn verify_and_sign(
-    author_data_message: &str,
-    post_data_message: &str,
+    author_data: VitaSignedSession,
+    post_data: VitaSignedSession,
+    trust_store: &NotaryTrustStore,
+    signing_key: &SigningKey,
+    scoring: &ScoringConfig,
+    target_policy: &RequestTargetPolicy,
+    transcript_limits: &TranscriptLimits,
+    redeem_store: &RedeemStore,
+    version: Option<RedeemCodeVersion>,
+    clock: &dyn Clock,
+    metrics: &Metrics,
 ) -> Result<Signedredeemcode, ApiError> {
-    let post_data: VitaSignedSession = deserialize_message(post_data_message)?;
-    let author_data: VitaSignedSession = deserialize_message(author_data_message)?;
-
     let VitaSignedSession {
+        meta: post_meta,
         signature: post_signature,
         application_data: post_application_data,
         attributes: post_attributes,
@@ -20,6 +40,7 @@ pub fn verify_and_sign(
     } = post_data;
 
     let VitaSignedSession {
+        meta: author_meta,
         attributes: author_attributes,
         signature: author_signature,
         application_data: author_application_data,
@@ -36,18 +57,41 @@ pub fn verify_and_sign(
             "Missing digital signature".to_string(),
         ));
     }
-    if post_attributes.is_empty() || author_attributes.is_empty() {
-        return Err(ApiError::InvalidMessage(
-            "Missing message attributes".to_string(),
-        ));
-    }
+    let now = clock.now();
+
+    let stage = info_span!("attribute_verify").entered();
+    // 根据 session 声明的 notary 选择公钥, 未知的 notary 直接拒绝
+    let post_notary_keys = trust_store.resolve(&post_meta.notary_url, now)?;
+    let author_notary_keys = trust_store.resolve(&author_meta.notary_url, now)?;
+
+    // application_data 只有在 notary 的 session 签名校验通过后才可信
+    let (post_notary_key, author_notary_key) = metrics.time(Stage::NotaryVerify, || {
+        let post_notary_key =
+            verify_session_signature(&post_notary_keys, &post_application_data, &post_signature)?;
+        let author_notary_key = verify_session_signature(
+            &author_notary_keys,
+            &author_application_data,
+            &author_signature,
+        )?;
+        Ok::<_, ApiError>((post_notary_key, author_notary_key))
+    })?;
 
-    // let decoded_data = decode_app_data(&post_application_data);
+    let (post_decoded_data, author_decoded_data) = metrics.time(Stage::TranscriptDecode, || {
+        Ok::<_, ApiError>((
+            decode_app_data(&post_application_data, transcript_limits)?,
+            decode_app_data(&author_application_data, transcript_limits)?,
+        ))
+    })?;
+
+    // post session 必须是 TweetDetail, author session 必须能证明登录用户
+    let focal_tweet_id = target_policy.check_post_session(&post_decoded_data)?;
+    target_policy.check_author_session(&author_decoded_data)?;
 
     let mut is_valid = true;
 
     for attribute in &post_attributes {
         let is_valid_ = verify_signature(
+            &post_notary_key,
             &attribute.attribute_hex,
             &attribute.attribute_name,
             &attribute.signature,
@@ -61,6 +105,7 @@ pub fn verify_and_sign(
 
     for attribute in &author_attributes {
         let is_valid_ = verify_signature(
+            &author_notary_key,
             &attribute.attribute_hex,
             &attribute.attribute_name,
             &attribute.signature,
@@ -78,15 +123,63 @@ pub fn verify_and_sign(
         ));
     }
 
-    let post_auhtor_id = find_author_attribute(&post_attributes)?;
-    let author_id = find_author_attribute(&author_attributes)?;
+    // 签名合法还不够, attribute 的值必须与响应中的 focal tweet 一致
+    let claims = TweetClaims::from_response(&post_decoded_data.response_body, &focal_tweet_id)?;
+    claims.check_attributes(&post_attributes)?;
+    stage.exit();
 
-    if post_auhtor_id != author_id {
-        return Err(ApiError::InvalidMessage(
+    let stage = info_span!("ca_extract").entered();
+    let ca = extract_ca(&claims.content)?;
+    stage.exit();
+
+    let stage = info_span!("author_match").entered();
+    let viewer_id = viewer_id_from_response(&author_decoded_data.response_body)?;
+    if claims.author_id != viewer_id {
+        return Err(ApiError::AuthorMismatch(
             "Author verification failed: post author does not match provided author".to_string(),
         ));
     }
+    stage.exit();
+
+    // campaign 由推文中的 CA 决定, 客户端不能选择更宽松的 campaign
+    let campaign = scoring.campaign_for_ca(&ca)?;
+    let scoring_policy = scoring.policy(campaign)?;
+    let params = IssueParams::new(version, campaign, scoring.default_validity_secs);
+
+    let _stage = info_span!("sign", post_id = %claims.post_id, campaign = %campaign.id).entered();
+    // 推文发布时间来自 notary 签名的响应, 不在 campaign 时间范围内的不签发
+    params.tweet_window.check(claims.created_at_unix()?, now)?;
+
+    let signed_redeemcode = metrics.time(Stage::Sign, || {
+        generate_redeemcode_and_sign(&claims, scoring_policy, signing_key, &params, now)
+    })?;
+
+    // 每个推文只签发一次, 重复提交按 ReplayPolicy 返回原来的兑换码或报错
+    let (issued, new) = redeem_store.issue(IssuedRedeemCode {
+        client: CLIENT.to_string(),
+        post_id: claims.post_id.clone(),
+        redeemcode: signed_redeemcode.redeemcode,
+        signature: signed_redeemcode.signature,
+        policy_id: scoring_policy.id.clone(),
+        key_id: signed_redeemcode.key_id,
+        issued_at: now,
+        revoked_at: None,
+        consumed_at: None,
+    })?;
+    if new {
+        metrics.record_issued(CLIENT, &campaign.id);
+    }
+    info!(
+        policy_id = %issued.policy_id,
+        key_id = %issued.key_id,
+        new,
+        "issued redeem code"
+    );
 
-    let signed_redeemcode = generate_redeemcode_and_sign(&post_attributes)?;
-    Ok(signed_redeemcode)
+    Ok(Signedredeemcode {
+        policy_id: signed_policy_id(&issued.redeemcode, &issued.policy_id),
+        redeemcode: issued.redeemcode,
+        signature: issued.signature,
+        key_id: issued.key_id,
+    })
 }
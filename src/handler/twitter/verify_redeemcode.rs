@@ -0,0 +1,593 @@
+use ed25519_dalek::Signature;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::error::ApiError;
+use crate::utils::clock::Clock;
+use crate::utils::redeem_store::RedeemStore;
+
+use super::generate_redeemcode_and_sign::{RedeemCodeVersion, Signedredeemcode, CLIENT};
+use super::redeem_code::RedeemCode;
+use super::redeem_code_v2::{RedeemCodeV2, V2_PREFIX};
+use super::redeem_keys::{KeyStatus, RedeemKeyring};
+
+/// 兑换码校验结果, 字段来自 `v1-twitter-<post>-<ca>-<engagement>-<key id>` 或 v2 的 CBOR,
+/// 轮换之前签发的兑换码没有 key ID
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RedeemCodeVerification {
+    pub version: String,
+    pub client: String,
+    pub post_id: String,
+    pub ca: String,
+    pub engagement: u64,
+    /// 验证通过的公钥
+    pub key_id: String,
+    pub key_status: KeyStatus,
+    /// 是否是本服务记录在案的兑换码
+    pub issued: bool,
+    pub policy_id: Option<String>,
+    pub issued_at: Option<u64>,
+    /// 只有 v2 兑换码带有以下字段
+    pub expires_at: Option<u64>,
+    pub not_before: Option<u64>,
+    pub campaign: Option<String>,
+    /// 兑换码本身被作废, 或者签名公钥被吊销
+    pub revoked: bool,
+    pub consumed: bool,
+}
+
+/// 校验兑换码签名并解析其中的字段, 同时返回签发记录中的状态
+///
+/// 不在有效期内, 或者签发时间不在公钥签名时间段内的兑换码直接拒绝
+pub fn verify_redeemcode(
+    signed: &Signedredeemcode,
+    keyring: &RedeemKeyring,
+    redeem_store: &RedeemStore,
+    clock: &dyn Clock,
+) -> Result<RedeemCodeVerification, ApiError> {
+    let (mut verification, code_key_id) = parse_redeemcode(&signed.redeemcode)?;
+
+    let signature = hex::decode(&signed.signature)
+        .ok()
+        .and_then(|bytes| Signature::from_slice(&bytes).ok())
+        .ok_or_else(|| ApiError::InvalidRedeemCode("signature is not valid hex".to_string()))?;
+    // 只有与记录完全一致的兑换码才算本服务签发的
+    let issued = redeem_store
+        .get(&verification.client, &verification.post_id)?
+        .filter(|issued| issued.redeemcode == signed.redeemcode);
+    // v1 兑换码不带签发时间, 只能取签发记录中的时间; 两者都没有时不检查公钥的签名时间段
+    let issued_at = verification
+        .issued_at
+        .or(issued.as_ref().map(|issued| issued.issued_at));
+
+    // 退役的公钥签发的兑换码依然有效, 吊销的公钥签发的兑换码标记为 revoked
+    let key = keyring.verify(
+        signed.redeemcode.as_bytes(),
+        &signature,
+        code_key_id.as_deref(),
+        issued_at,
+    )?;
+    verification.key_id = key.key_id.clone();
+    verification.key_status = key.status;
+    verification.revoked = key.status == KeyStatus::Revoked;
+
+    // 有效期在签名覆盖的范围内, 签名通过之后才可信
+    let now = clock.now();
+    if let Some(not_before) = verification
+        .not_before
+        .filter(|&not_before| now < not_before)
+    {
+        return Err(ApiError::RedeemCodeNotYetValid(format!(
+            "Redeem code is not valid until {}",
+            not_before
+        )));
+    }
+    if let Some(expires_at) = verification
+        .expires_at
+        .filter(|&expires_at| now >= expires_at)
+    {
+        return Err(ApiError::RedeemCodeExpired(format!(
+            "Redeem code expired at {}",
+            expires_at
+        )));
+    }
+
+    if let Some(issued) = issued {
+        verification.issued = true;
+        verification.policy_id = Some(issued.policy_id);
+        verification.issued_at = Some(issued.issued_at);
+        verification.revoked |= issued.revoked_at.is_some();
+        verification.consumed = issued.consumed_at.is_some();
+    }
+
+    Ok(verification)
+}
+
+/// 兑换兑换码: 先按 [`verify_redeemcode`] 校验, 再在存储中原子地标记为已兑换
+///
+/// 只有本服务签发、未作废且未兑换过的兑换码才能兑换
+pub fn consume_redeemcode(
+    signed: &Signedredeemcode,
+    keyring: &RedeemKeyring,
+    redeem_store: &RedeemStore,
+    clock: &dyn Clock,
+) -> Result<RedeemCodeVerification, ApiError> {
+    let mut verification = verify_redeemcode(signed, keyring, redeem_store, clock)?;
+    // 签名公钥被吊销
+    if verification.key_status == KeyStatus::Revoked {
+        return Err(ApiError::RedeemCodeRevoked(format!(
+            "Redeem code was signed by revoked key {}",
+            verification.key_id
+        )));
+    }
+
+    let issued = redeem_store.consume(
+        &verification.client,
+        &verification.post_id,
+        &signed.redeemcode,
+        clock.now(),
+    )?;
+    verification.issued = true;
+    verification.policy_id = Some(issued.policy_id);
+    verification.issued_at = Some(issued.issued_at);
+    verification.consumed = true;
+
+    Ok(verification)
+}
+
+/// 返回解析出的字段以及兑换码中的 key ID
+fn parse_redeemcode(
+    redeemcode: &str,
+) -> Result<(RedeemCodeVerification, Option<String>), ApiError> {
+    let invalid_code = |reason: &str| ApiError::InvalidRedeemCode(reason.to_string());
+
+    if redeemcode.starts_with(V2_PREFIX) {
+        let code = RedeemCodeV2::decode(redeemcode)?;
+        if code.client != CLIENT {
+            return Err(invalid_code("unsupported client"));
+        }
+
+        let verification = RedeemCodeVerification {
+            version: RedeemCodeVersion::V2.as_str().to_string(),
+            client: code.client,
+            post_id: code.post_id,
+            ca: code.ca,
+            engagement: code.engagement,
+            key_id: String::new(),
+            key_status: KeyStatus::Active,
+            issued: false,
+            policy_id: Some(code.policy_id),
+            issued_at: Some(code.issued_at),
+            expires_at: code.expires_at,
+            not_before: code.not_before,
+            campaign: code.campaign,
+            revoked: false,
+            consumed: false,
+        };
+        return Ok((verification, Some(code.key_id)));
+    }
+
+    let code = redeemcode.parse::<RedeemCode>()?;
+    let verification = RedeemCodeVerification {
+        version: RedeemCodeVersion::V1.as_str().to_string(),
+        client: code.client,
+        post_id: code.post_id,
+        ca: code.ca,
+        engagement: code.engagement,
+        key_id: String::new(),
+        key_status: KeyStatus::Active,
+        issued: false,
+        policy_id: None,
+        issued_at: None,
+        expires_at: None,
+        not_before: None,
+        campaign: None,
+        revoked: false,
+        consumed: false,
+    };
+
+    Ok((verification, code.key_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::twitter::generate_redeemcode_and_sign::{
+        generate_redeemcode_and_sign, IssueParams,
+    };
+    use crate::handler::twitter::redeem_keys::{key_id, test_keyring, test_signing_key};
+    use crate::handler::twitter::scoring_policy::ScoringConfig;
+    use crate::handler::twitter::tweet_claims::TweetClaims;
+    use crate::utils::clock::{ManualClock, SystemClock};
+    use crate::utils::redeem_store::{IssuedRedeemCode, ReplayPolicy};
+    use ed25519_dalek::{Signer, SigningKey};
+
+    /// 轮换后的新私钥
+    fn rotated_signing_key() -> SigningKey {
+        SigningKey::from_bytes(&[7u8; 32])
+    }
+
+    /// 测试兑换码的签发时间
+    const ISSUED_AT: u64 = 1_736_932_298;
+    /// 签发测试兑换码一小时后轮换私钥
+    const ROTATED_AT: u64 = ISSUED_AT + 3600;
+
+    /// 新私钥为 active, 原来的测试私钥为 `old_status`, 在 [`ROTATED_AT`] 交接
+    fn rotated_keyring(old_status: &str) -> RedeemKeyring {
+        let public_key = |signing_key: &SigningKey| {
+            bs58::encode(signing_key.verifying_key().as_bytes()).into_string()
+        };
+        RedeemKeyring::from_json(&format!(
+            r#"{{"keys": [
+                {{"public_key": "{}", "status": "active", "not_before": {}, "not_after": null}},
+                {{"public_key": "{}", "status": "{}", "not_before": null, "not_after": {}}}
+            ]}}"#,
+            public_key(&rotated_signing_key()),
+            ROTATED_AT,
+            public_key(&test_signing_key()),
+            old_status,
+            ROTATED_AT
+        ))
+        .unwrap()
+    }
+
+    fn signed() -> Signedredeemcode {
+        signed_with(&IssueParams {
+            version: RedeemCodeVersion::V1,
+            ..Default::default()
+        })
+    }
+
+    fn signed_with(params: &IssueParams) -> Signedredeemcode {
+        signed_by(&test_signing_key(), params, ISSUED_AT)
+    }
+
+    fn signed_by(
+        signing_key: &SigningKey,
+        params: &IssueParams,
+        issued_at: u64,
+    ) -> Signedredeemcode {
+        let claims = TweetClaims {
+            post_id: "1879456397454385265".to_string(),
+            author_id: "1248668065148973061".to_string(),
+            content: "ca:6p6xgHyF7AeE6TZkSmFsko444wqoP15icUSqi2jfGiPN".to_string(),
+            created_at: "Wed Jan 15 09:11:38 +0000 2025".to_string(),
+            bookmark_count: 1,
+            favorite_count: 2,
+            retweet_count: 3,
+            reply_count: 0,
+            quote_count: 0,
+            view_count: None,
+        };
+        let config = ScoringConfig::builtin().unwrap();
+        generate_redeemcode_and_sign(
+            &claims,
+            config.policy(config.campaign("default").unwrap()).unwrap(),
+            signing_key,
+            params,
+            issued_at,
+        )
+        .unwrap()
+    }
+
+    fn issued(signed: &Signedredeemcode, revoked_at: Option<u64>) -> IssuedRedeemCode {
+        IssuedRedeemCode {
+            client: "twitter".to_string(),
+            post_id: "1879456397454385265".to_string(),
+            redeemcode: signed.redeemcode.clone(),
+            signature: signed.signature.clone(),
+            policy_id: "legacy".to_string(),
+            key_id: signed.key_id.clone(),
+            issued_at: ISSUED_AT,
+            revoked_at,
+            consumed_at: None,
+        }
+    }
+
+    #[test]
+    fn test_verify_redeemcode() {
+        let signed = signed();
+        let keyring = test_keyring();
+        let store = RedeemStore::in_memory(ReplayPolicy::ReturnExisting).unwrap();
+
+        // 签名正确但没有签发记录
+        let verification = verify_redeemcode(&signed, &keyring, &store, &SystemClock).unwrap();
+        assert_eq!(verification.post_id, "1879456397454385265");
+        assert_eq!(
+            verification.ca,
+            "6p6xgHyF7AeE6TZkSmFsko444wqoP15icUSqi2jfGiPN"
+        );
+        assert_eq!(verification.engagement, 7);
+        assert_eq!(verification.key_id, signed.key_id);
+        assert_eq!(verification.key_id, key_id(&keyring.active().verifying_key));
+        assert!(!verification.issued);
+
+        store.issue(issued(&signed, Some(1_736_932_300))).unwrap();
+        let verification = verify_redeemcode(&signed, &keyring, &store, &SystemClock).unwrap();
+        assert!(verification.issued);
+        assert!(verification.revoked);
+        assert!(!verification.consumed);
+        assert_eq!(verification.policy_id.as_deref(), Some("legacy"));
+    }
+
+    #[test]
+    fn test_consume_redeemcode() {
+        let signed = signed();
+        let keyring = test_keyring();
+        let store = RedeemStore::in_memory(ReplayPolicy::ReturnExisting).unwrap();
+        let clock = ManualClock::new(1_736_932_300);
+
+        // 没有签发记录的兑换码不能兑换
+        assert!(matches!(
+            consume_redeemcode(&signed, &keyring, &store, &clock),
+            Err(ApiError::NotFound(_))
+        ));
+
+        store.issue(issued(&signed, None)).unwrap();
+        let verification = consume_redeemcode(&signed, &keyring, &store, &clock).unwrap();
+        assert!(verification.issued);
+        assert!(verification.consumed);
+        assert!(
+            verify_redeemcode(&signed, &keyring, &store, &clock)
+                .unwrap()
+                .consumed
+        );
+        assert!(matches!(
+            consume_redeemcode(&signed, &keyring, &store, &clock),
+            Err(ApiError::RedeemCodeConsumed(_))
+        ));
+
+        // 签名公钥被吊销后不能兑换
+        let other = RedeemStore::in_memory(ReplayPolicy::ReturnExisting).unwrap();
+        other.issue(issued(&signed, None)).unwrap();
+        assert!(matches!(
+            consume_redeemcode(&signed, &rotated_keyring("revoked"), &other, &clock),
+            Err(ApiError::RedeemCodeRevoked(_))
+        ));
+        assert!(other
+            .get("twitter", "1879456397454385265")
+            .unwrap()
+            .unwrap()
+            .consumed_at
+            .is_none());
+    }
+
+    #[test]
+    fn test_verify_redeemcode_v2() {
+        let signed = signed_with(&IssueParams {
+            version: RedeemCodeVersion::V2,
+            campaign: Some("launch".to_string()),
+            ..Default::default()
+        });
+        let keyring = test_keyring();
+        let store = RedeemStore::in_memory(ReplayPolicy::ReturnExisting).unwrap();
+
+        let verification = verify_redeemcode(&signed, &keyring, &store, &SystemClock).unwrap();
+        assert_eq!(verification.version, "v2");
+        assert_eq!(verification.post_id, "1879456397454385265");
+        assert_eq!(verification.engagement, 7);
+        assert_eq!(verification.key_id, signed.key_id);
+        assert_eq!(verification.campaign.as_deref(), Some("launch"));
+        assert_eq!(verification.policy_id.as_deref(), Some("legacy"));
+        assert_eq!(verification.issued_at, Some(1_736_932_298));
+        assert!(!verification.issued);
+
+        store.issue(issued(&signed, None)).unwrap();
+        assert!(
+            verify_redeemcode(&signed, &keyring, &store, &SystemClock)
+                .unwrap()
+                .issued
+        );
+
+        // 签名覆盖整个文本, 换一个有效的 v2 兑换码也无法通过
+        let mut swapped = signed.clone();
+        swapped.redeemcode = signed_with(&IssueParams {
+            version: RedeemCodeVersion::V2,
+            ..Default::default()
+        })
+        .redeemcode;
+        assert!(matches!(
+            verify_redeemcode(&swapped, &keyring, &store, &SystemClock),
+            Err(ApiError::SignatureError(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_redeemcode_validity_window() {
+        let keyring = test_keyring();
+        let store = RedeemStore::in_memory(ReplayPolicy::ReturnExisting).unwrap();
+        // 签发于 1_736_932_298, 一小时后生效, 生效后一天过期
+        let signed = signed_with(&IssueParams {
+            version: RedeemCodeVersion::V2,
+            validity_secs: Some(86_400),
+            not_before: Some(1_736_932_298 + 3600),
+            ..Default::default()
+        });
+
+        let clock = ManualClock::new(1_736_932_298);
+        assert!(matches!(
+            verify_redeemcode(&signed, &keyring, &store, &clock),
+            Err(ApiError::RedeemCodeNotYetValid(_))
+        ));
+
+        clock.advance(3600);
+        let verification = verify_redeemcode(&signed, &keyring, &store, &clock).unwrap();
+        assert_eq!(verification.not_before, Some(1_736_935_898));
+        assert_eq!(verification.expires_at, Some(1_737_022_298));
+
+        clock.advance(86_399);
+        assert!(verify_redeemcode(&signed, &keyring, &store, &clock).is_ok());
+        clock.advance(1);
+        assert!(matches!(
+            verify_redeemcode(&signed, &keyring, &store, &clock),
+            Err(ApiError::RedeemCodeExpired(_))
+        ));
+
+        // 过期时间被篡改时签名校验失败
+        let code = RedeemCodeV2::decode(&signed.redeemcode).unwrap();
+        let extended = Signedredeemcode {
+            redeemcode: RedeemCodeV2 {
+                expires_at: Some(u64::MAX),
+                ..code
+            }
+            .encode(),
+            ..signed
+        };
+        assert!(matches!(
+            verify_redeemcode(&extended, &keyring, &store, &clock),
+            Err(ApiError::SignatureError(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_redeemcode_after_rotation() {
+        let store = RedeemStore::in_memory(ReplayPolicy::ReturnExisting).unwrap();
+        let old = signed();
+
+        // 退役公钥签发的兑换码依然有效
+        let keyring = rotated_keyring("retired");
+        let verification = verify_redeemcode(&old, &keyring, &store, &SystemClock).unwrap();
+        assert_eq!(verification.key_id, old.key_id);
+        assert_eq!(verification.key_status, KeyStatus::Retired);
+        assert!(!verification.revoked);
+
+        // 吊销公钥后, 它签发的兑换码全部作废
+        let keyring = rotated_keyring("revoked");
+        let verification = verify_redeemcode(&old, &keyring, &store, &SystemClock).unwrap();
+        assert_eq!(verification.key_status, KeyStatus::Revoked);
+        assert!(verification.revoked);
+
+        // 兑换码中的 key ID 必须与签名的公钥一致
+        let mut wrong_key_id = old.clone();
+        let new_key_id = keyring.active().key_id.clone();
+        wrong_key_id.redeemcode = old.redeemcode.replace(&old.key_id, &new_key_id);
+        wrong_key_id.signature = format!(
+            "{:x}",
+            test_signing_key().sign(wrong_key_id.redeemcode.as_bytes())
+        );
+        assert!(matches!(
+            verify_redeemcode(&wrong_key_id, &keyring, &store, &SystemClock),
+            Err(ApiError::SignatureError(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_redeemcode_outside_key_window() {
+        let store = RedeemStore::in_memory(ReplayPolicy::ReturnExisting).unwrap();
+        let keyring = rotated_keyring("retired");
+        let v2 = IssueParams::default();
+
+        // 签发时间在各自公钥的签名时间段内
+        let old = signed_by(&test_signing_key(), &v2, ROTATED_AT - 1);
+        let verification = verify_redeemcode(&old, &keyring, &store, &SystemClock).unwrap();
+        assert_eq!(verification.key_status, KeyStatus::Retired);
+        let new = signed_by(&rotated_signing_key(), &v2, ROTATED_AT);
+        let verification = verify_redeemcode(&new, &keyring, &store, &SystemClock).unwrap();
+        assert_eq!(verification.key_status, KeyStatus::Active);
+
+        // 泄露的旧私钥在轮换之后签发, 或者新私钥在生效之前签发
+        for signed in [
+            signed_by(&test_signing_key(), &v2, ROTATED_AT),
+            signed_by(&rotated_signing_key(), &v2, ROTATED_AT - 1),
+        ] {
+            assert!(matches!(
+                verify_redeemcode(&signed, &keyring, &store, &SystemClock),
+                Err(ApiError::SignatureError(_))
+            ));
+        }
+
+        // v1 兑换码不带签发时间, 有签发记录时按记录中的时间检查
+        let v1 = IssueParams {
+            version: RedeemCodeVersion::V1,
+            ..Default::default()
+        };
+        let late = signed_by(&test_signing_key(), &v1, ROTATED_AT);
+        assert!(verify_redeemcode(&late, &keyring, &store, &SystemClock).is_ok());
+        store
+            .issue(IssuedRedeemCode {
+                issued_at: ROTATED_AT,
+                ..issued(&late, None)
+            })
+            .unwrap();
+        assert!(matches!(
+            verify_redeemcode(&late, &keyring, &store, &SystemClock),
+            Err(ApiError::SignatureError(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_legacy_redeemcode_without_key_id() {
+        let store = RedeemStore::in_memory(ReplayPolicy::ReturnExisting).unwrap();
+        let redeemcode =
+            "v1-twitter-111111111111111-6p6xgHyF7AeE6TZkSmFsko444wqoP15icUSqi2jfGiPN-1";
+        let legacy = Signedredeemcode {
+            redeemcode: redeemcode.to_string(),
+            signature: format!("{:x}", test_signing_key().sign(redeemcode.as_bytes())),
+            policy_id: None,
+            key_id: String::new(),
+        };
+
+        let verification =
+            verify_redeemcode(&legacy, &rotated_keyring("retired"), &store, &SystemClock).unwrap();
+        assert_eq!(
+            verification.key_id,
+            key_id(&test_signing_key().verifying_key())
+        );
+        assert_eq!(verification.key_status, KeyStatus::Retired);
+    }
+
+    #[test]
+    fn test_verify_redeemcode_rejects_tampering() {
+        let keyring = test_keyring();
+        let store = RedeemStore::in_memory(ReplayPolicy::ReturnExisting).unwrap();
+
+        let mut tampered = signed();
+        tampered.redeemcode = tampered.redeemcode.replace("-7", "-7000");
+        assert!(matches!(
+            verify_redeemcode(&tampered, &keyring, &store, &SystemClock),
+            Err(ApiError::SignatureError(_))
+        ));
+
+        let mut bad_signature = signed();
+        bad_signature.signature = "zz".to_string();
+        assert!(matches!(
+            verify_redeemcode(&bad_signature, &keyring, &store, &SystemClock),
+            Err(ApiError::InvalidRedeemCode(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_redeemcode() {
+        let (_, key_id) = parse_redeemcode(
+            "v1-twitter-111111111111111-6p6xgHyF7AeE6TZkSmFsko444wqoP15icUSqi2jfGiPN-1",
+        )
+        .unwrap();
+        assert_eq!(key_id, None);
+        let (_, key_id) = parse_redeemcode(
+            "v1-twitter-111111111111111-6p6xgHyF7AeE6TZkSmFsko444wqoP15icUSqi2jfGiPN-1-3a0e06ba",
+        )
+        .unwrap();
+        assert_eq!(key_id.as_deref(), Some("3a0e06ba"));
+
+        let cases = [
+            "v1-twitter-111-ca",
+            "v1-twitter-111-ca-1-3a0e06ba-extra",
+            "v1-twitter-111-ca-1-",
+            "v0-twitter-111-ca-1",
+            "v1-tiktok-111-ca-1",
+            "v1-twitter-abc-ca-1",
+            "v1-twitter-111--1",
+            "v1-twitter-111-ca--1",
+        ];
+        for redeemcode in cases {
+            assert!(
+                matches!(
+                    parse_redeemcode(redeemcode),
+                    Err(ApiError::InvalidRedeemCode(_))
+                ),
+                "{} should be rejected",
+                redeemcode
+            );
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use utoipa::ToSchema;
+
+use crate::error::ApiError;
+use crate::utils::deserialize_message::*;
+
+/// `POST /verify` 的请求体
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(untagged)]
+pub enum VerifyRequest {
+    /// 按名字区分两个 session, 不会因为顺序传错
+    Named {
+        /// 证明登录用户身份的 session
+        author_session: SessionInput,
+        /// 证明推文内容的 session
+        post_session: SessionInput,
+    },
+    /// 旧格式 `[author_session, post_session]`, 两个 session 都是 JSON 字符串
+    Legacy([String; 2]),
+}
+
+/// session 可以直接嵌入 JSON 对象, 也可以是 JSON 字符串
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(untagged)]
+pub enum SessionInput {
+    Text(String),
+    #[schema(value_type = VitaSignedSession)]
+    Object(Map<String, Value>),
+}
+
+impl SessionInput {
+    pub fn into_session(self) -> Result<VitaSignedSession, ApiError> {
+        match self {
+            SessionInput::Text(message) if message.is_empty() => {
+                Err(ApiError::InvalidMessage("Invalid parameters".to_string()))
+            }
+            SessionInput::Text(message) => deserialize_message(&message),
+            SessionInput::Object(object) => serde_json::from_value(Value::Object(object))
+                .map_err(|err| ApiError::InvalidJson(format!("Invalid session object: {}", err))),
+        }
+    }
+}
+
+impl VerifyRequest {
+    /// 返回 `(author_session, post_session)`
+    pub fn into_sessions(self) -> Result<(VitaSignedSession, VitaSignedSession), ApiError> {
+        let (author_session, post_session) = match self {
+            VerifyRequest::Named {
+                author_session,
+                post_session,
+            } => (author_session, post_session),
+            VerifyRequest::Legacy([author_session, post_session]) => (
+                SessionInput::Text(author_session),
+                SessionInput::Text(post_session),
+            ),
+        };
+
+        Ok((author_session.into_session()?, post_session.into_session()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(notary_url: &str) -> Value {
+        serde_json::json!({
+            "version": "1.0",
+            "meta": { "notaryUrl": notary_url, "websocketProxyUrl": "wss://proxy.example.com" },
+            "signature": "signature",
+            "application_data": "data",
+            "attributes": []
+        })
+    }
+
+    fn sessions(body: Value) -> Result<(VitaSignedSession, VitaSignedSession), ApiError> {
+        serde_json::from_value::<VerifyRequest>(body)
+            .unwrap()
+            .into_sessions()
+    }
+
+    #[test]
+    fn test_request_forms() {
+        let author = session("https://author.example.com");
+        let post = session("https://post.example.com");
+
+        let bodies = [
+            serde_json::json!({ "author_session": author, "post_session": post }),
+            serde_json::json!({
+                "post_session": post.to_string(),
+                "author_session": author.to_string()
+            }),
+            serde_json::json!({ "author_session": author, "post_session": post.to_string() }),
+            serde_json::json!([author.to_string(), post.to_string()]),
+        ];
+        for body in bodies {
+            let (author, post) = sessions(body).unwrap();
+            assert_eq!(author.meta.notary_url, "https://author.example.com");
+            assert_eq!(post.meta.notary_url, "https://post.example.com");
+        }
+    }
+
+    #[test]
+    fn test_invalid_requests() {
+        assert!(matches!(
+            sessions(serde_json::json!(["", session("n").to_string()])),
+            Err(ApiError::InvalidMessage(_))
+        ));
+        assert!(matches!(
+            sessions(serde_json::json!({ "author_session": {}, "post_session": session("n") })),
+            Err(ApiError::InvalidJson(_))
+        ));
+
+        // 缺少字段、单个 session 或三个元素的数组都不是合法的请求体
+        for body in [
+            serde_json::json!({ "author_session": session("n") }),
+            serde_json::json!([session("n").to_string()]),
+            serde_json::json!(["a", "b", "c"]),
+            serde_json::json!({ "author_session": 1, "post_session": 2 }),
+        ] {
+            assert!(serde_json::from_value::<VerifyRequest>(body).is_err());
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use ed25519_dalek::{Signer, SigningKey};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::error::ApiError;
+use crate::handler::twitter::generate_redeemcode_and_sign::CLIENT;
+use crate::handler::twitter::redeem_code::RedeemCode;
+use crate::handler::twitter::redeem_keys::{KeyStatus, RedeemKeyring};
+use crate::utils::notary_trust_store::NotaryTrustStore;
+use crate::utils::redeem_store::RedeemStore;
+use crate::utils::verify_signature::notary_self_check;
+
+/// 自检时签名的兑换码, 不会写入存储
+const KNOWN_ANSWER_POST_ID: &str = "1";
+const KNOWN_ANSWER_CA: &str = "6p6xgHyF7AeE6TZkSmFsko444wqoP15icUSqi2jfGiPN";
+
+/// `GET /readyz` 的响应, 每次请求都重新检查
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Readiness {
+    /// 所有检查都通过
+    pub ready: bool,
+    pub checks: Vec<CheckResult>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CheckResult {
+    pub name: String,
+    pub ok: bool,
+    /// 失败原因, 通过时为 `null`
+    pub error: Option<String>,
+}
+
+impl CheckResult {
+    fn new(name: &str, result: Result<(), ApiError>) -> Self {
+        CheckResult {
+            name: name.to_string(),
+            ok: result.is_ok(),
+            error: result.err().map(|err| err.to_string()),
+        }
+    }
+}
+
+/// 就绪检查: 密钥、notary 信任列表、兑换码存储以及两个已知向量自检
+pub fn check_readiness(
+    trust_store: &NotaryTrustStore,
+    signing_key: &SigningKey,
+    keyring: &RedeemKeyring,
+    redeem_store: &RedeemStore,
+    now: u64,
+) -> Readiness {
+    let checks = vec![
+        CheckResult::new("signing_key", keyring.check_signing_key(signing_key)),
+        CheckResult::new("trust_store", check_trust_store(trust_store, now)),
+        CheckResult::new("redeem_store", redeem_store.check_writable(now)),
+        CheckResult::new(
+            "redeem_code_kat",
+            redeem_code_kat(signing_key, keyring, now),
+        ),
+        CheckResult::new("notary_kat", notary_self_check(trust_store, now)),
+    ];
+
+    Readiness {
+        ready: checks.iter().all(|check| check.ok),
+        checks,
+    }
+}
+
+/// 至少有一个 notary 的公钥在当前时刻有效, 否则所有 session 都会被拒绝
+fn check_trust_store(trust_store: &NotaryTrustStore, now: u64) -> Result<(), ApiError> {
+    let has_valid_key = trust_store
+        .notaries()
+        .iter()
+        .flat_map(|notary| &notary.keys)
+        .any(|key| key.is_valid_at(now));
+    if !has_valid_key {
+        return Err(ApiError::UntrustedNotary(
+            "Trust store has no notary key valid at this time".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// 用签名私钥签一个固定的兑换码, 再用公钥列表验证, 必须由 active 公钥验证通过
+///
+/// 按当前时刻签发验证, active 公钥的签名时间段不包含当前时刻时, 新签发的兑换码都无法通过验证
+fn redeem_code_kat(
+    signing_key: &SigningKey,
+    keyring: &RedeemKeyring,
+    now: u64,
+) -> Result<(), ApiError> {
+    let key_id = keyring.active().key_id.clone();
+    let redeemcode = RedeemCode {
+        client: CLIENT.to_string(),
+        post_id: KNOWN_ANSWER_POST_ID.to_string(),
+        ca: KNOWN_ANSWER_CA.to_string(),
+        engagement: 0,
+        key_id: Some(key_id.clone()),
+    }
+    .to_string();
+
+    // 格式化后的兑换码必须能被解析回来
+    redeemcode.parse::<RedeemCode>()?;
+    let signature = signing_key.sign(redeemcode.as_bytes());
+    match keyring.verify(redeemcode.as_bytes(), &signature, Some(&key_id), Some(now)) {
+        Ok(key) if key.status == KeyStatus::Active => Ok(()),
+        _ => Err(ApiError::SigningKeyError(
+            "Known-answer redeem code did not verify with the active key".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use p256::pkcs8::EncodePublicKey;
+    use rand_core::OsRng;
+
+    use super::*;
+    use crate::handler::twitter::redeem_keys::{test_keyring, test_keyring_json, test_signing_key};
+    use crate::utils::notary_trust_store::now_unix;
+    use crate::utils::redeem_store::ReplayPolicy;
+
+    fn failed_checks(readiness: &Readiness) -> Vec<&str> {
+        readiness
+            .checks
+            .iter()
+            .filter(|check| !check.ok)
+            .map(|check| check.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_ready() {
+        let readiness = check_readiness(
+            &NotaryTrustStore::example().unwrap(),
+            &test_signing_key(),
+            &test_keyring(),
+            &RedeemStore::in_memory(ReplayPolicy::ReturnExisting).unwrap(),
+            now_unix(),
+        );
+        assert!(readiness.ready, "{:?}", readiness);
+        assert_eq!(readiness.checks.len(), 5);
+    }
+
+    #[test]
+    fn test_not_ready() {
+        let store = RedeemStore::in_memory(ReplayPolicy::ReturnExisting).unwrap();
+
+        // 签名私钥与 active 公钥不一致
+        let readiness = check_readiness(
+            &NotaryTrustStore::example().unwrap(),
+            &SigningKey::from_bytes(&[7u8; 32]),
+            &test_keyring(),
+            &store,
+            now_unix(),
+        );
+        assert!(!readiness.ready);
+        assert_eq!(
+            failed_checks(&readiness),
+            ["signing_key", "redeem_code_kat"]
+        );
+
+        // notary 公钥已过期, 也不能验证已知向量
+        let pem = p256::ecdsa::SigningKey::random(&mut OsRng)
+            .verifying_key()
+            .to_public_key_pem(Default::default())
+            .unwrap();
+        let json = serde_json::json!({
+            "notaries": [{ "id": "expired", "urls": ["https://expired.example.com"], "keys": [{ "public_key_pem": pem, "not_before": null, "not_after": 1 }] }]
+        })
+        .to_string();
+        let readiness = check_readiness(
+            &NotaryTrustStore::from_json(&json).unwrap(),
+            &test_signing_key(),
+            &test_keyring(),
+            &store,
+            now_unix(),
+        );
+        assert!(!readiness.ready);
+        assert_eq!(failed_checks(&readiness), ["trust_store", "notary_kat"]);
+
+        // active 公钥的签名时间段已经结束, 新签发的兑换码都无法通过验证
+        let json = test_keyring_json().replace(r#""not_after":null"#, r#""not_after":1"#);
+        let readiness = check_readiness(
+            &NotaryTrustStore::example().unwrap(),
+            &test_signing_key(),
+            &RedeemKeyring::from_json(&json).unwrap(),
+            &store,
+            now_unix(),
+        );
+        assert!(!readiness.ready);
+        assert_eq!(failed_checks(&readiness), ["redeem_code_kat"]);
+    }
+}
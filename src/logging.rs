@@ -0,0 +1,210 @@
+use axum::http::{HeaderName, Request};
+use axum::Router;
+use serde::{Deserialize, Serialize};
+use tower_http::request_id::{
+    MakeRequestUuid, PropagateRequestIdLayer, RequestId, SetRequestIdLayer,
+};
+use tower_http::trace::{DefaultOnResponse, TraceLayer};
+use tracing::{Level, Span};
+use tracing_subscriber::EnvFilter;
+
+use crate::error::ApiError;
+use crate::utils::deserialize_message::VitaSignedSession;
+
+/// 请求 ID 所在的请求头与响应头, 客户端传入时沿用客户端的值
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// 日志输出格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// 给人看的单行文本
+    #[default]
+    Text,
+    /// 每行一个 JSON 对象, 带上当前请求的所有 span 字段
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = ApiError;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(ApiError::ConfigError(format!(
+                "Unknown log format {}",
+                format
+            ))),
+        }
+    }
+}
+
+/// 初始化全局 subscriber, `filter` 使用 `EnvFilter` 的语法
+pub fn init(format: LogFormat, filter: &str) -> Result<(), ApiError> {
+    let filter = EnvFilter::try_new(filter)
+        .map_err(|err| ApiError::ConfigError(format!("Invalid log filter {}: {}", filter, err)))?;
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    let result = match format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .try_init(),
+    };
+
+    result.map_err(|err| ApiError::ConfigError(format!("Cannot initialize logging: {}", err)))
+}
+
+/// 为每个请求生成请求 ID, 写入响应头, 并在 `request` span 中记录
+pub fn with_request_tracing<S>(router: Router<S>) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    let header = HeaderName::from_static(REQUEST_ID_HEADER);
+
+    // 后添加的 layer 在外层: 先生成请求 ID, 再创建 span, 最后把 ID 写回响应
+    router
+        .layer(PropagateRequestIdLayer::new(header.clone()))
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(request_span)
+                .on_response(DefaultOnResponse::new().level(Level::INFO)),
+        )
+        .layer(SetRequestIdLayer::new(header, MakeRequestUuid))
+}
+
+fn request_span<B>(request: &Request<B>) -> Span {
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .and_then(|id| id.header_value().to_str().ok())
+        .unwrap_or_default();
+
+    // 只记录方法与 URI, 请求体由各个 handler 按脱敏配置记录
+    tracing::info_span!(
+        "request",
+        request_id,
+        method = %request.method(),
+        uri = %request.uri(),
+    )
+}
+
+/// 记录收到的两个 session
+///
+/// session 中包含推文内容与 notary 签名, 开启脱敏时只记录 notary 与数据大小
+pub fn log_sessions(author: &VitaSignedSession, post: &VitaSignedSession, redact: bool) {
+    if redact {
+        tracing::debug!(
+            author_notary = %author.meta.notary_url,
+            author_attributes = author.attributes.len(),
+            author_data_len = author.application_data.len(),
+            post_notary = %post.meta.notary_url,
+            post_attributes = post.attributes.len(),
+            post_data_len = post.application_data.len(),
+            "received sessions"
+        );
+    } else {
+        tracing::debug!(?author, ?post, "received sessions");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::body::Body;
+    use axum::routing::get;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// 以 JSON 格式捕获 `f` 中输出的日志
+    fn capture_logs(f: impl FnOnce()) -> String {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .json()
+            .with_max_level(Level::DEBUG)
+            .with_writer(move || writer.clone())
+            .finish();
+        tracing::subscriber::with_default(subscriber, f);
+
+        let logs = captured.0.lock().unwrap().clone();
+        String::from_utf8(logs).unwrap()
+    }
+
+    fn session() -> VitaSignedSession {
+        serde_json::from_value(serde_json::json!({
+            "version": "1.0",
+            "meta": { "notaryUrl": "https://notary.example.com", "websocketProxyUrl": "wss://proxy" },
+            "signature": "secret-signature",
+            "application_data": "secret-tweet-content",
+            "attributes": []
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_log_sessions_redaction() {
+        let session = session();
+
+        let redacted = capture_logs(|| log_sessions(&session, &session, true));
+        assert!(redacted.contains("https://notary.example.com"));
+        assert!(redacted.contains("\"post_data_len\":20"));
+        assert!(!redacted.contains("secret-signature"));
+        assert!(!redacted.contains("secret-tweet-content"));
+
+        let full = capture_logs(|| log_sessions(&session, &session, false));
+        assert!(full.contains("secret-signature"));
+    }
+
+    #[tokio::test]
+    async fn test_request_id_header() {
+        let app = with_request_tracing(Router::new().route("/", get(|| async { "ok" })));
+
+        let response = app
+            .clone()
+            .oneshot(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let request_id = response.headers()[REQUEST_ID_HEADER].to_str().unwrap();
+        assert_eq!(request_id.len(), 36);
+
+        // 客户端传入的请求 ID 原样返回, 方便跨服务关联日志
+        let response = app
+            .oneshot(
+                Request::get("/")
+                    .header(REQUEST_ID_HEADER, "client-id-1")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "client-id-1");
+    }
+
+    #[test]
+    fn test_log_format() {
+        assert_eq!("json".parse::<LogFormat>().unwrap(), LogFormat::Json);
+        assert!(matches!(
+            "yaml".parse::<LogFormat>(),
+            Err(ApiError::ConfigError(_))
+        ));
+    }
+}
@@ -1,61 +1,538 @@
 // crates/api-server/src/main.rs
+mod config;
 mod error;
 mod handler;
+mod health;
+mod logging;
+mod metrics;
+mod openapi;
+mod response;
 mod utils;
 
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::config::{Cli, Config};
+use crate::error::ApiError;
+use crate::handler::twitter::generate_redeemcode_and_sign::{
+    RedeemCodeVersion, Signedredeemcode, CLIENT,
+};
+use crate::handler::twitter::redeem_keys::RedeemKeyring;
+use crate::handler::twitter::redeem_keys::{keyring_entry, Jwks};
+use crate::handler::twitter::request_target::RequestTargetPolicy;
+use crate::handler::twitter::scoring_policy::ScoringConfig;
 use crate::handler::twitter::verify_and_sign::*;
+use crate::handler::twitter::verify_redeemcode::RedeemCodeVerification;
+use crate::handler::twitter::verify_redeemcode::{consume_redeemcode, verify_redeemcode};
+use crate::handler::twitter::verify_request::VerifyRequest;
+use crate::health::{check_readiness, Readiness};
+use crate::metrics::Metrics;
+use crate::openapi::ApiDoc;
+use crate::response::{respond, ApiResponse, ErrorResponse};
+use crate::utils::admin_token::check_bearer_token;
+use crate::utils::clock::{Clock, SystemClock};
+use crate::utils::http_transcript::TranscriptLimits;
+use crate::utils::notary_trust_store::{now_unix, NotaryTrustStore};
+use crate::utils::redeem_store::{IssuedRedeemCode, RedeemStore};
+use crate::utils::verify_signature::notary_self_check;
+use ed25519_dalek::SigningKey;
+
+const CURRENT_VERSION: &str = "v2";
+
+use axum::extract::rejection::JsonRejection;
+use axum::extract::DefaultBodyLimit;
+use axum::extract::{Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware;
+use axum::{response::IntoResponse, Json};
+use axum::{
+    routing::{get, post},
+    Router,
+};
+use clap::Parser;
+use serde::Deserialize;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+#[derive(Clone)]
+struct AppState {
+    trust_store: Arc<NotaryTrustStore>,
+    signing_key: Arc<SigningKey>,
+    scoring: Arc<ScoringConfig>,
+    request_target: Arc<RequestTargetPolicy>,
+    transcript_limits: Arc<TranscriptLimits>,
+    redeem_store: Arc<RedeemStore>,
+    redeem_keyring: Arc<RedeemKeyring>,
+    clock: Arc<dyn Clock>,
+    metrics: Arc<Metrics>,
+    /// 错误响应使用旧格式, 见 [`respond`]
+    legacy_envelope: bool,
+    /// 日志中不记录 session 内容, 见 [`logging::log_sessions`]
+    redact_sessions: bool,
+    /// 管理接口的 bearer token, 不配置时管理接口不可用
+    admin_token: Option<Arc<str>>,
+}
 
-const CURRENT_VERSION: &str = "v1";
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct VerifyQuery {
+    /// 兑换码格式版本 (`v1` / `v2`), 不指定时由 campaign 的有效期决定
+    version: Option<String>,
+}
 
-use axum::{http::StatusCode, response::IntoResponse, Json};
-use axum::{routing::post, Router};
+/// 作废某个推文的兑换码
+#[derive(Deserialize, ToSchema)]
+struct RevokeRequest {
+    post_id: String,
+}
 
-#[derive(serde::Serialize)]
-struct ApiResponse<T> {
-    code: u16,
-    message: String,
-    data: Option<T>,
+impl VerifyQuery {
+    fn version(&self) -> Result<Option<RedeemCodeVersion>, ApiError> {
+        self.version
+            .as_deref()
+            .map(str::parse::<RedeemCodeVersion>)
+            .transpose()
+    }
 }
 
 #[tokio::main]
 async fn main() {
-    let app = Router::new().route("/verify", post(verify_handler));
-
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:7049").await.unwrap();
-    println!("listening on {}", listener.local_addr().unwrap());
-    axum::serve(listener, app).await.unwrap();
-}
-
-async fn verify_handler(Json(payload): Json<(String, String)>) -> impl IntoResponse {
-    println!("Received request with payload: {:?}", payload);
-    println!("payload.0: {:?}", payload.0);
-    println!("payload.1: {:?}", payload.1);
-
-    if payload.0.is_empty() || payload.1.is_empty() {
-        println!("Invalid parameters received");
-        return Json(ApiResponse {
-            code: StatusCode::BAD_REQUEST.as_u16(),
-            message: "Invalid parameters".to_string(),
-            data: None,
-        });
-    }
-
-    match verify_and_sign(&payload.0, &payload.1) {
-        Ok(response) => {
-            println!("Request processed successfully");
-            Json(ApiResponse {
-                code: StatusCode::OK.as_u16(),
-                message: "success".to_string(),
-                data: Some(response),
+    let cli = Cli::parse();
+    if cli.print_public_key {
+        let signing_key = or_exit(
+            Config::merge(&cli, |name| std::env::var(name).ok())
+                .and_then(|config| config.keys.signing_key_source())
+                .and_then(|source| source.load()),
+            "Failed to load redeem signing key",
+        );
+        println!("{}", keyring_entry(&signing_key));
+        return;
+    }
+    let config = or_exit(
+        Config::load(&cli, |name| std::env::var(name).ok()),
+        "Invalid configuration",
+    );
+    if cli.print_config {
+        print!("{}", config.redacted().to_toml());
+        return;
+    }
+    or_exit(
+        logging::init(config.log.format, &config.log.filter),
+        "Failed to initialize logging",
+    );
+
+    // 密钥只在启动时读取一次, 配置错误时直接退出, 避免所有请求都验证失败
+    let trust_store_path = config
+        .notary
+        .trust_store_path
+        .as_deref()
+        .expect("notary.trust_store_path is checked by Config::validate");
+    let trust_store = or_exit(
+        NotaryTrustStore::load(trust_store_path),
+        &format!(
+            "Failed to load notary trust store {}",
+            trust_store_path.display()
+        ),
+    );
+    or_exit(
+        notary_self_check(&trust_store, now_unix()),
+        &format!(
+            "Notary public key self-check failed for {}",
+            trust_store_path.display()
+        ),
+    );
+    let signing_key = or_exit(
+        config
+            .keys
+            .signing_key_source()
+            .and_then(|source| source.load()),
+        "Failed to load redeem signing key",
+    );
+    let keyring_path = config
+        .keys
+        .keyring_path
+        .as_deref()
+        .expect("keys.keyring_path is checked by Config::validate");
+    let redeem_keyring = or_exit(
+        RedeemKeyring::load(keyring_path),
+        &format!("Failed to load redeem keyring {}", keyring_path.display()),
+    );
+    or_exit(
+        redeem_keyring.check_signing_key(&signing_key),
+        &format!(
+            "Redeem signing key self-check against {} failed",
+            keyring_path.display()
+        ),
+    );
+    let scoring_path = config.scoring.config_path.as_deref();
+    let scoring = or_exit(
+        ScoringConfig::load(scoring_path),
+        &format!(
+            "Failed to load scoring config {}",
+            scoring_path.map_or("(built-in)".into(), Path::to_string_lossy)
+        ),
+    );
+    let redeem_store = or_exit(
+        RedeemStore::open(
+            &config.storage.redeem_store_path,
+            config.storage.replay_policy,
+        ),
+        &format!(
+            "Failed to open redeem store {}",
+            config.storage.redeem_store_path.display()
+        ),
+    );
+
+    let state = AppState {
+        trust_store: Arc::new(trust_store),
+        signing_key: Arc::new(signing_key),
+        scoring: Arc::new(scoring),
+        request_target: Arc::new(config.request_target.clone()),
+        transcript_limits: Arc::new(config.transcript.clone()),
+        redeem_store: Arc::new(redeem_store),
+        redeem_keyring: Arc::new(redeem_keyring),
+        clock: Arc::new(SystemClock),
+        metrics: Arc::new(Metrics::new()),
+        legacy_envelope: config.server.legacy_envelope,
+        redact_sessions: config.log.redact_sessions,
+        admin_token: config.server.admin_token.as_deref().map(Arc::from),
+    };
+    let app = logging::with_request_tracing(
+        Router::new()
+            .route("/verify", post(verify_handler))
+            .route("/redeem/verify", post(redeem_verify_handler))
+            .route("/redeem/consume", post(redeem_consume_handler))
+            .route("/redeem/revoke", post(redeem_revoke_handler))
+            .route("/keys", get(keys_handler))
+            .route("/openapi.json", get(openapi_handler))
+            .route("/metrics", get(metrics_handler))
+            .route("/healthz", get(healthz_handler))
+            .route("/readyz", get(readyz_handler))
+            .route_layer(middleware::from_fn_with_state(
+                state.metrics.clone(),
+                metrics::track_requests,
+            )),
+    )
+    .layer(DefaultBodyLimit::max(config.server.max_body_bytes))
+    .with_state(state);
+
+    let listen_addr = or_exit(config.listen_addr(), "Invalid listen address");
+    let listener = or_exit(
+        tokio::net::TcpListener::bind(listen_addr).await,
+        &format!("Cannot listen on {}", listen_addr),
+    );
+    tracing::info!(addr = %listen_addr, "listening");
+    or_exit(axum::serve(listener, app).await, "Server error");
+}
+
+/// 启动失败时打印原因并以状态码 1 退出, 不输出 panic 信息
+fn or_exit<T, E: std::fmt::Display>(result: Result<T, E>, context: &str) -> T {
+    match result {
+        Ok(value) => value,
+        Err(err) => {
+            eprintln!("{}: {}", context, err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// 校验 author 与 post 两个 session, 为推文签发兑换码
+#[utoipa::path(
+    post,
+    path = "/verify",
+    params(VerifyQuery),
+    request_body = VerifyRequest,
+    responses(
+        (status = 200, description = "签发的兑换码", body = ApiResponse<Signedredeemcode>),
+        (status = 400, description = "请求或 session 格式错误", body = ErrorResponse),
+        (status = 401, description = "notary 签名或 transcript 校验失败", body = ErrorResponse),
+        (status = 403, description = "notary 不受信任或作者不一致", body = ErrorResponse),
+        (status = 404, description = "没有 campaign 接受推文中的 CA, 或推文数据不存在", body = ErrorResponse),
+        (status = 409, description = "该推文已经签发过兑换码", body = ErrorResponse),
+        (status = 422, description = "没有 CA、推文不在 campaign 时间范围内或互动分数不足", body = ErrorResponse),
+        (status = 500, description = "服务端错误", body = ErrorResponse),
+    )
+)]
+async fn verify_handler(
+    State(state): State<AppState>,
+    Query(query): Query<VerifyQuery>,
+    payload: Result<Json<VerifyRequest>, JsonRejection>,
+) -> impl IntoResponse {
+    let result = match payload {
+        Ok(Json(payload)) => {
+            let state = state.clone();
+            run_blocking(move || verify(&state, &query, payload)).await
+        }
+        Err(rejection) => Err(invalid_json(rejection)),
+    };
+    if let Err(err) = &result {
+        log_error("verification failed", err);
+        state.metrics.record_failure("/verify", err);
+    }
+
+    respond(result, state.legacy_envelope)
+}
+
+fn verify(
+    state: &AppState,
+    query: &VerifyQuery,
+    payload: VerifyRequest,
+) -> Result<Signedredeemcode, ApiError> {
+    let (author_session, post_session) =
+        tracing::info_span!("deserialize").in_scope(|| payload.into_sessions())?;
+    logging::log_sessions(&author_session, &post_session, state.redact_sessions);
+    verify_and_sign(
+        author_session,
+        post_session,
+        &state.trust_store,
+        &state.signing_key,
+        &state.scoring,
+        &state.request_target,
+        &state.transcript_limits,
+        &state.redeem_store,
+        query.version()?,
+        state.clock.as_ref(),
+        &state.metrics,
+    )
+}
+
+/// 校验兑换码签名, 返回解析出的字段与签发状态
+#[utoipa::path(
+    post,
+    path = "/redeem/verify",
+    request_body = Signedredeemcode,
+    responses(
+        (status = 200, description = "兑换码校验结果", body = ApiResponse<RedeemCodeVerification>),
+        (status = 400, description = "兑换码格式错误", body = ErrorResponse),
+        (status = 401, description = "签名校验失败", body = ErrorResponse),
+        (status = 403, description = "兑换码还没到生效时间", body = ErrorResponse),
+        (status = 410, description = "兑换码已过期", body = ErrorResponse),
+        (status = 500, description = "服务端错误", body = ErrorResponse),
+    )
+)]
+async fn redeem_verify_handler(
+    State(state): State<AppState>,
+    payload: Result<Json<Signedredeemcode>, JsonRejection>,
+) -> impl IntoResponse {
+    let result = match payload {
+        Ok(Json(payload)) => {
+            let state = state.clone();
+            run_blocking(move || {
+                verify_redeemcode(
+                    &payload,
+                    &state.redeem_keyring,
+                    &state.redeem_store,
+                    state.clock.as_ref(),
+                )
             })
+            .await
         }
-        Err(err) => {
-            println!("Error processing request: {}", err);
-            Json(ApiResponse {
-                code: StatusCode::BAD_REQUEST.as_u16(),
-                message: err.to_string(),
-                data: None,
+        Err(rejection) => Err(invalid_json(rejection)),
+    };
+    if let Err(err) = &result {
+        log_error("redeem code verification failed", err);
+        state.metrics.record_failure("/redeem/verify", err);
+    }
+
+    respond(result, state.legacy_envelope)
+}
+
+/// 兑换兑换码, 每个兑换码只能成功兑换一次
+#[utoipa::path(
+    post,
+    path = "/redeem/consume",
+    request_body = Signedredeemcode,
+    responses(
+        (status = 200, description = "兑换成功", body = ApiResponse<RedeemCodeVerification>),
+        (status = 400, description = "兑换码格式错误", body = ErrorResponse),
+        (status = 401, description = "签名校验失败", body = ErrorResponse),
+        (status = 403, description = "兑换码还没到生效时间", body = ErrorResponse),
+        (status = 404, description = "兑换码不是本服务签发的", body = ErrorResponse),
+        (status = 409, description = "兑换码已经兑换过", body = ErrorResponse),
+        (status = 410, description = "兑换码已过期或已作废", body = ErrorResponse),
+        (status = 500, description = "服务端错误", body = ErrorResponse),
+    )
+)]
+async fn redeem_consume_handler(
+    State(state): State<AppState>,
+    payload: Result<Json<Signedredeemcode>, JsonRejection>,
+) -> impl IntoResponse {
+    let result = match payload {
+        Ok(Json(payload)) => {
+            let state = state.clone();
+            run_blocking(move || {
+                consume_redeemcode(
+                    &payload,
+                    &state.redeem_keyring,
+                    &state.redeem_store,
+                    state.clock.as_ref(),
+                )
             })
+            .await
         }
+        Err(rejection) => Err(invalid_json(rejection)),
+    };
+    if let Err(err) = &result {
+        log_error("redeem code consumption failed", err);
+        state.metrics.record_failure("/redeem/consume", err);
     }
+
+    respond(result, state.legacy_envelope)
+}
+
+/// 作废某个推文的兑换码, 需要 `Authorization: Bearer <server.admin_token>`
+#[utoipa::path(
+    post,
+    path = "/redeem/revoke",
+    request_body = RevokeRequest,
+    responses(
+        (status = 200, description = "作废后的签发记录", body = ApiResponse<IssuedRedeemCode>),
+        (status = 400, description = "请求格式错误", body = ErrorResponse),
+        (status = 401, description = "缺少或错误的管理 token", body = ErrorResponse),
+        (status = 404, description = "该推文没有签发过兑换码", body = ErrorResponse),
+        (status = 500, description = "服务端错误", body = ErrorResponse),
+    )
+)]
+async fn redeem_revoke_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    payload: Result<Json<RevokeRequest>, JsonRejection>,
+) -> impl IntoResponse {
+    let result = match check_bearer_token(&headers, state.admin_token.as_deref())
+        .and(payload.map_err(invalid_json))
+    {
+        Ok(Json(payload)) => {
+            let state = state.clone();
+            run_blocking(move || {
+                state
+                    .redeem_store
+                    .revoke(CLIENT, &payload.post_id, state.clock.now())
+            })
+            .await
+        }
+        Err(err) => Err(err),
+    };
+    match &result {
+        Ok(issued) => tracing::info!(post_id = %issued.post_id, "redeem code revoked"),
+        Err(err) => {
+            log_error("redeem code revocation failed", err);
+            state.metrics.record_failure("/redeem/revoke", err);
+        }
+    }
+
+    respond(result, state.legacy_envelope)
+}
+
+/// 在阻塞线程池中执行会读写 redb 的逻辑, 写事务提交时的 fsync 不会占住 async worker
+///
+/// 沿用当前请求的 span, 日志中依然带有请求 ID
+async fn run_blocking<T, F>(f: F) -> Result<T, ApiError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, ApiError> + Send + 'static,
+{
+    let span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || span.in_scope(f))
+        .await
+        .unwrap_or_else(|err| {
+            Err(ApiError::StoreError(format!(
+                "Blocking task failed: {}",
+                err
+            )))
+        })
+}
+
+/// 客户端错误记为 warn, 服务端错误记为 error
+fn log_error(message: &str, err: &ApiError) {
+    if err.status().is_server_error() {
+        tracing::error!(code = %err.code(), error = %err, "{}", message);
+    } else {
+        tracing::warn!(code = %err.code(), error = %err, "{}", message);
+    }
+}
+
+fn invalid_json(rejection: JsonRejection) -> ApiError {
+    ApiError::InvalidJson(rejection.body_text())
+}
+
+/// 兑换码签名公钥, JWKS 格式, 不包在 ApiResponse 中
+#[utoipa::path(
+    get,
+    path = "/keys",
+    responses((status = 200, description = "兑换码签名公钥", body = Jwks))
+)]
+async fn keys_handler(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.redeem_keyring.jwks())
+}
+
+/// 本服务的 OpenAPI 3 文档
+#[utoipa::path(
+    get,
+    path = "/openapi.json",
+    responses((status = 200, description = "OpenAPI 文档", content_type = "application/json"))
+)]
+async fn openapi_handler() -> impl IntoResponse {
+    Json(ApiDoc::openapi())
+}
+
+/// Prometheus 文本格式的指标
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses((status = 200, description = "Prometheus 指标", content_type = "text/plain"))
+)]
+async fn metrics_handler(State(state): State<AppState>) -> impl IntoResponse {
+    // gauge 在抓取时计算, 没有 not_before 的 key 无法计算使用时长, 不输出
+    let active = state.redeem_keyring.active();
+    if let Some(not_before) = active.not_before {
+        let age = state.clock.now().saturating_sub(not_before);
+        state.metrics.set_signing_key_age(&active.key_id, age);
+    }
+    match state.redeem_store.len() {
+        Ok(codes) => state.metrics.set_store_size(codes),
+        Err(err) => log_error("failed to count redeem codes", &err),
+    }
+
+    (
+        [(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)],
+        state.metrics.encode(),
+    )
+}
+
+/// 存活检查, 进程能处理请求即返回 200
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    responses((status = 200, description = "服务存活", content_type = "text/plain"))
+)]
+async fn healthz_handler() -> impl IntoResponse {
+    "ok"
+}
+
+/// 就绪检查, 每次请求都重新检查密钥、信任列表、存储与已知向量
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    responses(
+        (status = 200, description = "所有检查通过", body = Readiness),
+        (status = 503, description = "至少一项检查失败", body = Readiness),
+    )
+)]
+async fn readyz_handler(State(state): State<AppState>) -> impl IntoResponse {
+    let readiness = check_readiness(
+        &state.trust_store,
+        &state.signing_key,
+        &state.redeem_keyring,
+        &state.redeem_store,
+        state.clock.now(),
+    );
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        for check in readiness.checks.iter().filter(|check| !check.ok) {
+            tracing::error!(check = %check.name, error = ?check.error, "readiness check failed");
+        }
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (status, Json(readiness))
 }
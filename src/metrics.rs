@@ -0,0 +1,270 @@
+use std::time::Instant;
+
+use axum::extract::{MatchedPath, Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+
+use crate::error::ApiError;
+
+/// 耗时单独统计的校验阶段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// notary 的 session 签名校验
+    NotaryVerify,
+    /// 解码 application_data 中的 HTTP transcript
+    TranscriptDecode,
+    /// 生成并签名兑换码
+    Sign,
+}
+
+impl Stage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stage::NotaryVerify => "notary_verify",
+            Stage::TranscriptDecode => "transcript_decode",
+            Stage::Sign => "sign",
+        }
+    }
+}
+
+/// 服务的 Prometheus 指标, 使用独立的 registry, 测试之间互不影响
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    request_duration: HistogramVec,
+    issued_codes: IntCounterVec,
+    failures: IntCounterVec,
+    stage_duration: HistogramVec,
+    signing_key_age: IntGaugeVec,
+    store_size: IntGauge,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let requests = IntCounterVec::new(
+            Opts::new(
+                "tee_verify_http_requests_total",
+                "HTTP requests by route and status",
+            ),
+            &["route", "status"],
+        )
+        .unwrap();
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "tee_verify_http_request_duration_seconds",
+                "HTTP request latency by route",
+            ),
+            &["route"],
+        )
+        .unwrap();
+        let issued_codes = IntCounterVec::new(
+            Opts::new(
+                "tee_verify_redeem_codes_issued_total",
+                "Newly issued redeem codes by client and campaign",
+            ),
+            &["client", "campaign"],
+        )
+        .unwrap();
+        let failures = IntCounterVec::new(
+            Opts::new(
+                "tee_verify_failures_total",
+                "Failed requests by route and error code",
+            ),
+            &["route", "kind"],
+        )
+        .unwrap();
+        let stage_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "tee_verify_stage_duration_seconds",
+                "Time spent in each verification stage",
+            )
+            .buckets(prometheus::exponential_buckets(0.0001, 4.0, 8).unwrap()),
+            &["stage"],
+        )
+        .unwrap();
+        let signing_key_age = IntGaugeVec::new(
+            Opts::new(
+                "tee_verify_signing_key_age_seconds",
+                "Seconds since the active signing key's not_before",
+            ),
+            &["kid"],
+        )
+        .unwrap();
+        let store_size = IntGauge::new(
+            "tee_verify_redeem_store_codes",
+            "Redeem codes in the redeem store",
+        )
+        .unwrap();
+
+        let registry = Registry::new();
+        // 指标名在上面写死, 注册只会因为重名失败
+        registry.register(Box::new(requests.clone())).unwrap();
+        registry
+            .register(Box::new(request_duration.clone()))
+            .unwrap();
+        registry.register(Box::new(issued_codes.clone())).unwrap();
+        registry.register(Box::new(failures.clone())).unwrap();
+        registry.register(Box::new(stage_duration.clone())).unwrap();
+        registry
+            .register(Box::new(signing_key_age.clone()))
+            .unwrap();
+        registry.register(Box::new(store_size.clone())).unwrap();
+
+        Metrics {
+            registry,
+            requests,
+            request_duration,
+            issued_codes,
+            failures,
+            stage_duration,
+            signing_key_age,
+            store_size,
+        }
+    }
+
+    /// 按 campaign 而不是 CA 计数: CA 来自推文内容, 数量没有上限,
+    /// campaign 只能是配置中的某一个
+    pub fn record_issued(&self, client: &str, campaign: &str) {
+        self.issued_codes
+            .with_label_values(&[client, campaign])
+            .inc();
+    }
+
+    pub fn record_failure(&self, route: &str, err: &ApiError) {
+        self.failures
+            .with_label_values(&[route, &err.code().to_string()])
+            .inc();
+    }
+
+    /// 统计 `f` 的耗时, 失败的调用也计入
+    pub fn time<T>(&self, stage: Stage, f: impl FnOnce() -> T) -> T {
+        let _timer = self
+            .stage_duration
+            .with_label_values(&[stage.as_str()])
+            .start_timer();
+        f()
+    }
+
+    /// 抓取时才计算的 gauge
+    pub fn set_signing_key_age(&self, kid: &str, age_secs: u64) {
+        // 轮换后只保留当前 active key
+        self.signing_key_age.reset();
+        self.signing_key_age
+            .with_label_values(&[kid])
+            .set(i64::try_from(age_secs).unwrap_or(i64::MAX));
+    }
+
+    pub fn set_store_size(&self, codes: u64) {
+        self.store_size
+            .set(i64::try_from(codes).unwrap_or(i64::MAX));
+    }
+
+    /// Prometheus 文本格式
+    pub fn encode(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("text encoding does not fail");
+        String::from_utf8(buffer).expect("text encoding is UTF-8")
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 统计每个路由的请求数、状态码与延迟
+///
+/// 通过 `route_layer` 添加, 只统计匹配到路由的请求; 标签使用路由模板, 避免标签数量无限增长
+pub async fn track_requests(
+    State(metrics): State<std::sync::Arc<Metrics>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let started = Instant::now();
+
+    let response = next.run(request).await;
+
+    metrics
+        .requests
+        .with_label_values(&[&route, response.status().as_str()])
+        .inc();
+    metrics
+        .request_duration
+        .with_label_values(&[&route])
+        .observe(started.elapsed().as_secs_f64());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::body::Body;
+    use axum::routing::get;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let metrics = Metrics::new();
+        metrics.record_issued("twitter", "default");
+        metrics.record_failure(
+            "/verify",
+            &ApiError::SignatureError("bad signature".to_string()),
+        );
+        let value = metrics.time(Stage::Sign, || 42);
+        assert_eq!(value, 42);
+        metrics.set_signing_key_age("3a0e06ba", 3600);
+        metrics.set_store_size(7);
+
+        let text = metrics.encode();
+        assert!(text.contains(
+            "tee_verify_redeem_codes_issued_total{campaign=\"default\",client=\"twitter\"} 1"
+        ));
+        assert!(text
+            .contains("tee_verify_failures_total{kind=\"SIGNATURE_INVALID\",route=\"/verify\"} 1"));
+        assert!(text.contains("tee_verify_stage_duration_seconds_count{stage=\"sign\"} 1"));
+        assert!(text.contains("tee_verify_signing_key_age_seconds{kid=\"3a0e06ba\"} 3600"));
+        assert!(text.contains("tee_verify_redeem_store_codes 7"));
+    }
+
+    #[tokio::test]
+    async fn test_track_requests() {
+        let metrics = Arc::new(Metrics::new());
+        let app = Router::new()
+            .route("/keys/:kid", get(|| async { "ok" }))
+            .route_layer(axum::middleware::from_fn_with_state(
+                metrics.clone(),
+                track_requests,
+            ));
+
+        for uri in ["/keys/a", "/keys/b", "/missing"] {
+            let request = axum::http::Request::get(uri).body(Body::empty()).unwrap();
+            app.clone().oneshot(request).await.unwrap();
+        }
+
+        // 标签使用路由模板; route_layer 不处理未匹配的请求
+        let text = metrics.encode();
+        assert!(
+            text.contains("tee_verify_http_requests_total{route=\"/keys/:kid\",status=\"200\"} 2")
+        );
+        assert!(
+            text.contains("tee_verify_http_request_duration_seconds_count{route=\"/keys/:kid\"} 2")
+        );
+        assert!(!text.contains("/missing"));
+    }
+}
@@ -0,0 +1,90 @@
+use utoipa::OpenApi;
+
+use crate::error::ErrorCode;
+use crate::handler::twitter::redeem_keys::{Jwk, KeyStatus};
+use crate::handler::twitter::verify_request::SessionInput;
+use crate::utils::deserialize_message::{Attribute, SessionMeta, VitaSignedSession};
+
+/// `GET /openapi.json` 返回的文档, 由各个 handler 上的 `#[utoipa::path]` 生成
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Vitameme TEE verify",
+        description = "Verifies notarized Twitter sessions and issues signed redeem codes"
+    ),
+    paths(
+        crate::verify_handler,
+        crate::redeem_verify_handler,
+        crate::redeem_consume_handler,
+        crate::redeem_revoke_handler,
+        crate::keys_handler,
+        crate::openapi_handler,
+        crate::metrics_handler,
+        crate::healthz_handler,
+        crate::readyz_handler
+    ),
+    components(schemas(
+        ErrorCode,
+        SessionInput,
+        VitaSignedSession,
+        SessionMeta,
+        Attribute,
+        KeyStatus,
+        Jwk
+    ))
+)]
+pub struct ApiDoc;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_openapi_document() {
+        let document = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        assert_eq!(document["openapi"].as_str().map(|v| &v[..2]), Some("3."));
+
+        for path in [
+            "/verify",
+            "/redeem/verify",
+            "/redeem/consume",
+            "/redeem/revoke",
+            "/keys",
+            "/openapi.json",
+            "/metrics",
+            "/healthz",
+            "/readyz",
+        ] {
+            assert!(document["paths"][path].is_object(), "{} is missing", path);
+        }
+
+        // 错误码列表与 ErrorCode 的序列化结果一致
+        let codes = document["components"]["schemas"]["ErrorCode"]["enum"]
+            .as_array()
+            .unwrap();
+        assert_eq!(codes.len(), 23);
+        assert!(codes.contains(&serde_json::json!("ALREADY_REDEEMED")));
+        assert_eq!(
+            serde_json::to_value(ErrorCode::CaNotFound).unwrap(),
+            "CA_NOT_FOUND"
+        );
+        assert!(codes.contains(&serde_json::json!("CA_NOT_FOUND")));
+        assert!(codes.contains(&serde_json::json!("ENGAGEMENT_TOO_LOW")));
+
+        let verify = &document["paths"]["/verify"]["post"];
+        let parameters: Vec<&str> = verify["parameters"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|parameter| parameter["name"].as_str())
+            .collect();
+        assert_eq!(parameters, ["version"]);
+        for status in ["200", "400", "401", "409", "422"] {
+            assert!(
+                verify["responses"][status].is_object(),
+                "{} is missing",
+                status
+            );
+        }
+    }
+}
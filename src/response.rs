@@ -0,0 +1,105 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::error::{ApiError, ErrorCode};
+
+/// 所有接口共用的响应格式
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiResponse<T> {
+    pub code: u16,
+    pub message: String,
+    pub data: Option<T>,
+}
+
+impl<T> ApiResponse<T> {
+    pub fn success(data: T) -> Self {
+        ApiResponse {
+            code: StatusCode::OK.as_u16(),
+            message: "success".to_string(),
+            data: Some(data),
+        }
+    }
+}
+
+/// 失败时的响应, 与 [`ApiResponse`] 字段相同, `code` 与 HTTP 状态码一致, 另带 `error` 错误码
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorResponse {
+    pub code: u16,
+    pub error: ErrorCode,
+    pub message: String,
+    /// 总是 `null`
+    #[schema(value_type = Option<Object>)]
+    pub data: Option<()>,
+}
+
+/// 把处理结果转换为响应
+///
+/// 旧格式下错误也返回 HTTP 200, `code` 固定为 400, 没有 `error` 字段,
+/// 给还没有适配 HTTP 状态码的客户端使用
+pub fn respond<T: Serialize>(result: Result<T, ApiError>, legacy_envelope: bool) -> Response {
+    match result {
+        Ok(data) => Json(ApiResponse::success(data)).into_response(),
+        Err(err) if legacy_envelope => Json(ApiResponse::<T> {
+            code: StatusCode::BAD_REQUEST.as_u16(),
+            message: err.to_string(),
+            data: None,
+        })
+        .into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn body_json(response: Response) -> serde_json::Value {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_respond() {
+        let response = respond(Ok("redeemcode"), false);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            body_json(response).await,
+            serde_json::json!({ "code": 200, "message": "success", "data": "redeemcode" })
+        );
+
+        let err = ApiError::AlreadyRedeemed("post 42".to_string());
+        let response = respond::<()>(Err(err), false);
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(
+            body_json(response).await,
+            serde_json::json!({
+                "code": 409,
+                "error": "ALREADY_REDEEMED",
+                "message": "Already Redeemed: post 42",
+                "data": null
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_respond_legacy_envelope() {
+        let err = ApiError::SignatureError("bad signature".to_string());
+        let response = respond::<()>(Err(err), true);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            body_json(response).await,
+            serde_json::json!({
+                "code": 400,
+                "message": "Signature Error: bad signature",
+                "data": null
+            })
+        );
+    }
+}
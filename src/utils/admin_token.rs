@@ -0,0 +1,57 @@
+use axum::http::{header, HeaderMap};
+use sha2::{Digest, Sha256};
+
+use crate::error::ApiError;
+
+/// 校验管理接口的 `Authorization: Bearer <token>` 请求头
+///
+/// 没有配置 token 时管理接口一律拒绝. 比较的是两边的 SHA-256, 耗时与 token 内容无关
+pub fn check_bearer_token(headers: &HeaderMap, expected: Option<&str>) -> Result<(), ApiError> {
+    let expected = expected
+        .ok_or_else(|| ApiError::Unauthorized("Admin endpoints are disabled".to_string()))?;
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or_else(|| ApiError::Unauthorized("Missing bearer token".to_string()))?;
+
+    if Sha256::digest(token.as_bytes()) != Sha256::digest(expected.as_bytes()) {
+        return Err(ApiError::Unauthorized("Invalid bearer token".to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(authorization: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, authorization.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_check_bearer_token() {
+        assert!(check_bearer_token(&headers("Bearer secret"), Some("secret")).is_ok());
+
+        let cases = [
+            (HeaderMap::new(), Some("secret")),
+            (headers("Bearer wrong"), Some("secret")),
+            (headers("secret"), Some("secret")),
+            (headers("Basic secret"), Some("secret")),
+            // 没有配置 token
+            (headers("Bearer secret"), None),
+        ];
+        for (headers, expected) in cases {
+            assert!(
+                matches!(
+                    check_bearer_token(&headers, expected),
+                    Err(ApiError::Unauthorized(_))
+                ),
+                "{:?} should be rejected",
+                headers
+            );
+        }
+    }
+}
@@ -0,0 +1,43 @@
+#[cfg(test)]
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::notary_trust_store::now_unix;
+
+/// 当前时间的来源, 测试中可以替换为手动推进的时钟
+pub trait Clock: Send + Sync {
+    /// 当前的 unix 时间 (秒)
+    fn now(&self) -> u64;
+}
+
+/// 系统时钟
+#[derive(Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        now_unix()
+    }
+}
+
+/// 只有调用 [`ManualClock::advance`] 时才会前进的时钟
+#[cfg(test)]
+#[derive(Debug)]
+pub struct ManualClock(AtomicU64);
+
+#[cfg(test)]
+impl ManualClock {
+    pub fn new(now: u64) -> Self {
+        ManualClock(AtomicU64::new(now))
+    }
+
+    pub fn advance(&self, secs: u64) {
+        self.0.fetch_add(secs, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+impl Clock for ManualClock {
+    fn now(&self) -> u64 {
+        self.0.load(Ordering::SeqCst)
+    }
+}
@@ -1,9 +1,10 @@
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 use crate::error::ApiError;
 
 /// The custom signed session
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct VitaSignedSession {
     /// The version of the session
     pub version: String,
@@ -18,7 +19,7 @@ pub struct VitaSignedSession {
 }
 
 /// The meta data of the session
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct SessionMeta {
     /// The url of the notary
     #[serde(rename = "notaryUrl")]
@@ -29,7 +30,7 @@ pub struct SessionMeta {
 }
 
 /// The attribute of the session
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct Attribute {
     /// The hex encoded attribute
     pub attribute_hex: String,
@@ -41,7 +42,7 @@ pub struct Attribute {
 
 pub fn deserialize_message(message: &str) -> Result<VitaSignedSession, ApiError> {
     let vita_signed_session: VitaSignedSession = serde_json::from_str(message).map_err(|_| {
-        ApiError::InvalidMessage("Invalid message format: please check your input".to_string())
+        ApiError::InvalidJson("Invalid message format: please check your input".to_string())
     })?;
     Ok(vita_signed_session)
 }
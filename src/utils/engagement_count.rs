@@ -0,0 +1,127 @@
+use serde_json::Value;
+
+use crate::error::ApiError;
+
+/// 解析互动数, 支持 Twitter 的缩写形式, 例如 `1.2K`、`3M`、`1,234`
+pub fn parse_engagement_count(value: &str) -> Result<u64, ApiError> {
+    let invalid_count = || ApiError::InvalidMessage(format!("Invalid engagement count: {}", value));
+
+    let value = value.trim().replace(',', "");
+    let (number, multiplier) = match value.chars().last() {
+        Some('k' | 'K') => (&value[..value.len() - 1], 1_000u64),
+        Some('m' | 'M') => (&value[..value.len() - 1], 1_000_000),
+        Some('b' | 'B') => (&value[..value.len() - 1], 1_000_000_000),
+        _ => (value.as_str(), 1),
+    };
+
+    let (integer, fraction) = number.split_once('.').unwrap_or((number, ""));
+    let is_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+    if integer.is_empty() || !is_digits(integer) || !is_digits(fraction) {
+        return Err(invalid_count());
+    }
+    // 没有单位时不允许小数, 有单位时小数位不能超过单位的精度
+    let scale = 10u64
+        .checked_pow(fraction.len() as u32)
+        .filter(|scale| *scale <= multiplier)
+        .ok_or_else(invalid_count)?;
+
+    let integer = integer
+        .parse::<u64>()
+        .ok()
+        .and_then(|integer| integer.checked_mul(multiplier))
+        .ok_or_else(invalid_count)?;
+    let fraction = if fraction.is_empty() {
+        0
+    } else {
+        fraction.parse::<u64>().map_err(|_| invalid_count())? * (multiplier / scale)
+    };
+
+    integer.checked_add(fraction).ok_or_else(invalid_count)
+}
+
+/// JSON 中的互动数可能是数字也可能是字符串 (例如 views.count)
+pub fn engagement_count_from_json(value: &Value) -> Result<u64, ApiError> {
+    match value {
+        Value::Number(number) => number.as_u64().ok_or_else(|| {
+            ApiError::InvalidMessage(format!("Invalid engagement count: {}", number))
+        }),
+        Value::String(count) => parse_engagement_count(count),
+        other => Err(ApiError::InvalidMessage(format!(
+            "Invalid engagement count: {}",
+            other
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_engagement_count() {
+        let cases = [
+            ("0", 0),
+            ("42", 42),
+            (" 1,234 ", 1_234),
+            ("1.2K", 1_200),
+            ("1.25k", 1_250),
+            ("3M", 3_000_000),
+            ("4.567M", 4_567_000),
+            ("2B", 2_000_000_000),
+            ("18446744073709551615", u64::MAX),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(
+                parse_engagement_count(value).unwrap(),
+                expected,
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_invalid_engagement_count() {
+        for value in [
+            "",
+            "-1",
+            "1.2",
+            "1.2345K",
+            "K",
+            ".5K",
+            "1.2.3K",
+            "abc",
+            "1e3",
+            "18446744073709551616",
+            "18446744073709551615K",
+        ] {
+            assert!(
+                matches!(
+                    parse_engagement_count(value),
+                    Err(ApiError::InvalidMessage(_))
+                ),
+                "{} should be rejected",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn test_engagement_count_from_json() {
+        assert_eq!(
+            engagement_count_from_json(&serde_json::json!(12)).unwrap(),
+            12
+        );
+        assert_eq!(
+            engagement_count_from_json(&serde_json::json!("1.2K")).unwrap(),
+            1_200
+        );
+        for value in [
+            serde_json::json!(-1),
+            serde_json::json!(1.5),
+            serde_json::json!(null),
+        ] {
+            assert!(engagement_count_from_json(&value).is_err());
+        }
+    }
+}
@@ -2,17 +2,30 @@ use regex::Regex;
 
 use crate::error::ApiError;
 
+/// EVM 地址或 44 位 base58 的 Solana 地址
+const CA_PATTERN: &str = r"(?:0x[a-fA-F0-9]{40}|[1-9A-HJ-NP-Za-km-z]{44})";
+
 pub fn extract_ca(text: &str) -> Result<String, ApiError> {
-    let re = Regex::new(
-        r"(?i)ca\s*:\s*(?:\\n\s*|\r?\n\s*)*((?:0x[a-fA-F0-9]{40}|[1-9A-HJ-NP-Za-km-z]{44})\b)",
-    )
+    let re = Regex::new(&format!(
+        r"(?i)ca\s*:\s*(?:\\n\s*|\r?\n\s*)*({}\b)",
+        CA_PATTERN
+    ))
     .unwrap();
     re.captures(text)
         .and_then(|caps| caps.get(1))
         .map(|m| m.as_str().to_string())
-        .ok_or_else(|| ApiError::NotFound("CA address not found in the message".to_string()))
+        .ok_or_else(|| ApiError::CaNotFound("CA address not found in the message".to_string()))
+}
+
+/// 整个字符串是否是一个合法的 CA 地址
+pub fn is_valid_ca(ca: &str) -> bool {
+    // 与 extract_ca 一样不区分大小写, 提取出的 CA 都能通过校验
+    Regex::new(&format!("(?i)^{}$", CA_PATTERN))
+        .unwrap()
+        .is_match(ca)
 }
 
+#[allow(clippy::bool_assert_comparison, clippy::redundant_pattern_matching)]
 #[test]
 fn test_extract_ca() {
     // 测试小写无空格
@@ -26,32 +39,32 @@ fn test_extract_ca() {
     // 测试大写有空格
     let text2 = "Start CA : ABC123 end";
     assert_eq!(
-        matches!(extract_ca(text2), Err(ApiError::NotFound(_))),
+        matches!(extract_ca(text2), Err(ApiError::CaNotFound(_))),
         true
     );
 
     // 测试混合大小写
     let text3 = "Test Ca:XYZ789 test";
     assert_eq!(
-        matches!(extract_ca(text3), Err(ApiError::NotFound(_))),
+        matches!(extract_ca(text3), Err(ApiError::CaNotFound(_))),
         true
     );
 
     // 测试没有 ca:
     let text4 = "Dobby thinks friends should always carry a sock";
     assert_eq!(
-        matches!(extract_ca(text4), Err(ApiError::NotFound(_))),
+        matches!(extract_ca(text4), Err(ApiError::CaNotFound(_))),
         true
     );
 
     let text5 = "Dobby thinks ca:xxxxxxxx friends should always carry a sock in their pocket—you never know when freedom might come knocking! \\n\\nDobby once used a teacup to solve a big problem, proving even the smallest things can hold great power.\"";
     assert_eq!(
-        matches!(extract_ca(text5), Err(ApiError::NotFound(_))),
+        matches!(extract_ca(text5), Err(ApiError::CaNotFound(_))),
         true
     );
     let text6 = "Dobby thinks friends should ca: always carry a sock";
     assert_eq!(
-        matches!(extract_ca(text6), Err(ApiError::NotFound(_))),
+        matches!(extract_ca(text6), Err(ApiError::CaNotFound(_))),
         true
     );
 
@@ -64,7 +77,7 @@ fn test_extract_ca() {
 
     // 测试无效格式，应该返回错误
     let text8 = "Start CA : ABC123 end";
-    assert!(matches!(extract_ca(text8), Err(ApiError::NotFound(_))));
+    assert!(matches!(extract_ca(text8), Err(ApiError::CaNotFound(_))));
 
     // 测试有效的 0x 格式
     let text9 = "Test Ca:0x85e58d0f9152669083bda1e6638fa6400898d0ee test";
@@ -1,103 +1,141 @@
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+
 use crate::error::ApiError;
 
-use super::Attribute;
+use super::{parse_engagement_count, Attribute};
+
+/// attribute 的值, `attribute_name` 的格式为 `key: <json value>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttributeValue {
+    /// 已经去掉 JSON 转义的字符串
+    String(String),
+    Integer(i64),
+}
+
+/// 按 key 索引的全部 attribute, key 不允许重复
+#[derive(Debug, Default)]
+pub struct ParsedAttributes {
+    values: BTreeMap<String, AttributeValue>,
+}
+
+/// 解析单个 `key: <json value>`, key 之后的部分按 JSON 解析, 因此值中可以包含冒号
+pub fn parse_attribute_name(attribute_name: &str) -> Result<(String, AttributeValue), ApiError> {
+    let (key, raw_value) = attribute_name.split_once(':').ok_or_else(|| {
+        ApiError::InvalidMessage(format!("Attribute {} is missing a value", attribute_name))
+    })?;
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(ApiError::InvalidMessage(
+            "Attribute key must not be empty".to_string(),
+        ));
+    }
+
+    let invalid_value =
+        || ApiError::InvalidMessage(format!("Attribute {} has an invalid value", key));
+    let value =
+        match serde_json::from_str::<Value>(raw_value.trim()).map_err(|_| invalid_value())? {
+            Value::String(value) => AttributeValue::String(value),
+            Value::Number(number) => number
+                .as_i64()
+                .map(AttributeValue::Integer)
+                .ok_or_else(invalid_value)?,
+            _ => return Err(invalid_value()),
+        };
+
+    Ok((key.to_string(), value))
+}
+
+/// 解析全部 attribute, 重复或冲突的 key 返回错误
+pub fn parse_attributes(attributes: &[Attribute]) -> Result<ParsedAttributes, ApiError> {
+    let mut values = BTreeMap::new();
+
+    for attribute in attributes {
+        let (key, value) = parse_attribute_name(&attribute.attribute_name)?;
+        if let Some(existing) = values.get(&key) {
+            let reason = if *existing == value {
+                "duplicate"
+            } else {
+                "conflicting"
+            };
+            return Err(ApiError::InvalidMessage(format!(
+                "Attribute {} has {} values",
+                key, reason
+            )));
+        }
+        values.insert(key, value);
+    }
+
+    Ok(ParsedAttributes { values })
+}
+
+impl ParsedAttributes {
+    pub fn get(&self, key: &str) -> Option<&AttributeValue> {
+        self.values.get(key)
+    }
+
+    fn find_string(&self, key: &str, missing: &str) -> Result<String, ApiError> {
+        match self.get(key) {
+            Some(AttributeValue::String(value)) => Ok(value.clone()),
+            Some(_) => Err(ApiError::InvalidMessage(format!(
+                "Attribute {} must be a string",
+                key
+            ))),
+            None => Err(ApiError::NotFound(missing.to_string())),
+        }
+    }
+
+    /// 互动数可以是整数, 也可以是 Twitter 的缩写形式的字符串, 例如 `"1.2K"`
+    fn find_count(&self, key: &str, missing: &str) -> Result<u64, ApiError> {
+        match self.get(key) {
+            Some(AttributeValue::Integer(value)) => u64::try_from(*value).map_err(|_| {
+                ApiError::InvalidMessage(format!("Attribute {} must not be negative", key))
+            }),
+            Some(AttributeValue::String(value)) => parse_engagement_count(value),
+            None => Err(ApiError::NotFound(missing.to_string())),
+        }
+    }
+}
 
 pub fn find_content_attribute(attributes: &[Attribute]) -> Result<String, ApiError> {
-    attributes
-        .iter()
-        .find(|attr| attr.attribute_name.starts_with("content:"))
-        .map(|attr| {
-            let content = attr
-                .attribute_name
-                .splitn(2, ':') // 最多分割成两部分
-                .nth(1) // 取第二部分
-                .unwrap_or("")
-                .trim()
-                .trim_matches('"')
-                .to_string();
-            content
-        })
-        .ok_or_else(|| ApiError::NotFound("Message content is missing".to_string()))
+    parse_attributes(attributes)?.find_string("content", "Message content is missing")
 }
 
 pub fn find_author_attribute(attributes: &[Attribute]) -> Result<String, ApiError> {
-    attributes
-        .iter()
-        .find(|attr| attr.attribute_name.starts_with("author:"))
-        .map(|attr| {
-            attr.attribute_name
-                .split(':') // 用冒号分割
-                .nth(1) // 取第二部分
-                .unwrap_or("") // 如果没有则返回空字符串
-                .trim() // 移除前后空白
-                .trim_matches('"') // 移除引号
-                .to_string()
-        })
-        .ok_or_else(|| ApiError::NotFound("Author information is missing".to_string()))
+    parse_attributes(attributes)?.find_string("author", "Author information is missing")
 }
 
 pub fn find_post_id_attribute(attributes: &[Attribute]) -> Result<String, ApiError> {
-    attributes
-        .iter()
-        .find(|attr| attr.attribute_name.starts_with("id:"))
-        .map(|attr| {
-            attr.attribute_name
-                .split(':') // 用冒号分割
-                .nth(1) // 取第二部分
-                .unwrap_or("") // 如果没有则返回空字符串
-                .trim() // 移除前后空白
-                .trim_matches('"') // 移除引号
-                .to_string()
-        })
-        .ok_or_else(|| ApiError::NotFound("Post ID is missing".to_string()))
-}
-
-pub fn find_bookmark_count_attribute(attributes: &[Attribute]) -> Result<String, ApiError> {
-    attributes
-        .iter()
-        .find(|attr| attr.attribute_name.starts_with("bookmark_count:"))
-        .map(|attr| {
-            attr.attribute_name
-                .split(':')
-                .nth(1)
-                .unwrap_or("")
-                .trim()
-                .trim_matches('"')
-                .to_string()
-        })
-        .ok_or_else(|| ApiError::NotFound("Bookmark count is missing".to_string()))
-}
-
-pub fn find_favorite_count_attribute(attributes: &[Attribute]) -> Result<String, ApiError> {
-    attributes
-        .iter()
-        .find(|attr| attr.attribute_name.starts_with("favorite_count:"))
-        .map(|attr| {
-            attr.attribute_name
-                .split(':') // 用冒号分割
-                .nth(1) // 取第二部分
-                .unwrap_or("") // 如果没有则返回空字符串
-                .trim() // 移除前后空白
-                .trim_matches('"') // 移除引号
-                .to_string()
-        })
-        .ok_or_else(|| ApiError::NotFound("Like count is missing".to_string()))
-}
-
-pub fn find_retweet_count_attribute(attributes: &[Attribute]) -> Result<String, ApiError> {
-    attributes
-        .iter()
-        .find(|attr| attr.attribute_name.starts_with("retweet_count:"))
-        .map(|attr| {
-            attr.attribute_name
-                .split(':') // 用冒号分割
-                .nth(1) // 取第二部分
-                .unwrap_or("") // 如果没有则返回空字符串
-                .trim() // 移除前后空白
-                .trim_matches('"') // 移除引号
-                .to_string()
-        })
-        .ok_or_else(|| ApiError::NotFound("Share count is missing".to_string()))
+    parse_attributes(attributes)?.find_string("id", "Post ID is missing")
+}
+
+pub fn find_created_at_attribute(attributes: &[Attribute]) -> Result<String, ApiError> {
+    parse_attributes(attributes)?.find_string("created_at", "Creation time is missing")
+}
+
+pub fn find_bookmark_count_attribute(attributes: &[Attribute]) -> Result<u64, ApiError> {
+    parse_attributes(attributes)?.find_count("bookmark_count", "Bookmark count is missing")
+}
+
+pub fn find_favorite_count_attribute(attributes: &[Attribute]) -> Result<u64, ApiError> {
+    parse_attributes(attributes)?.find_count("favorite_count", "Like count is missing")
+}
+
+pub fn find_retweet_count_attribute(attributes: &[Attribute]) -> Result<u64, ApiError> {
+    parse_attributes(attributes)?.find_count("retweet_count", "Share count is missing")
+}
+
+pub fn find_reply_count_attribute(attributes: &[Attribute]) -> Result<u64, ApiError> {
+    parse_attributes(attributes)?.find_count("reply_count", "Reply count is missing")
+}
+
+pub fn find_quote_count_attribute(attributes: &[Attribute]) -> Result<u64, ApiError> {
+    parse_attributes(attributes)?.find_count("quote_count", "Quote count is missing")
+}
+
+pub fn find_view_count_attribute(attributes: &[Attribute]) -> Result<u64, ApiError> {
+    parse_attributes(attributes)?.find_count("view_count", "View count is missing")
 }
 
 #[test]
@@ -112,11 +150,6 @@ fn test_find_content_attribute() {
             attribute_name: "dsant: \"Dobby thinks friends should always carry a sock in their pocket—you never know when freedom might come knocking! \\n\\nDobby once used a teacup to solve a big problem, proving even the smallest things can hold great power.\"".to_string(),
             signature: "test".to_string(),
         },
-        Attribute{
-            attribute_hex: "617574686f723a20223132343836363830363531343839373330363122".to_string(),
-            attribute_name: "dsant: \"Dobby thinks friends should always carry a sock in their pocket—you never know when freedom might come knocking! \\n\\nDobby once used a teacup to solve a big problem, proving even the smallest things can hold great power.\"".to_string(),
-            signature: "test".to_string(),
-        },
         Attribute{
             attribute_hex: "637265617465645f61743a2022576564204a616e2031352030393a31313a3338202b30303030203230323522".to_string(),
             attribute_name: "author: \"1248668065148973061\"".to_string(),
@@ -146,12 +179,18 @@ fn test_find_content_attribute() {
 
     let content = find_content_attribute(&attributes).expect("content not found");
     println!("content: is {:?}", content);
+    // 值中的冒号和 JSON 转义都应被正确处理
+    assert!(content.starts_with("Dobby thinks ca:xxxxxxxx friends"));
+    assert!(content.contains("knocking! \n\nDobby"));
+    assert!(content.ends_with("great power."));
 
     let author = find_author_attribute(&attributes).expect("author not found");
     println!("author: is {:?}", author);
+    assert_eq!(author, "1248668065148973061");
 
     let post_id = find_post_id_attribute(&attributes).expect("post_id not found");
     println!("post_id: is {:?}", post_id);
+    assert_eq!(post_id, "187945697454385265");
 
     let bookmark_count =
         find_bookmark_count_attribute(&attributes).expect("bookmark_count not found");
@@ -163,4 +202,94 @@ fn test_find_content_attribute() {
 
     let retweet_count = find_retweet_count_attribute(&attributes).expect("retweet_count not found");
     println!("retweet_count: is {:?}", retweet_count);
+    assert_eq!((bookmark_count, favorite_count, retweet_count), (0, 0, 0));
+}
+
+#[cfg(test)]
+fn attribute(attribute_name: &str) -> Attribute {
+    Attribute {
+        attribute_hex: hex::encode(attribute_name),
+        attribute_name: attribute_name.to_string(),
+        signature: "test".to_string(),
+    }
+}
+
+#[test]
+fn test_parse_attribute_name() {
+    assert_eq!(
+        parse_attribute_name("author: \"https://x.com/a:b\"").unwrap(),
+        (
+            "author".to_string(),
+            AttributeValue::String("https://x.com/a:b".to_string())
+        )
+    );
+    assert_eq!(
+        parse_attribute_name("content: \"line\\n\\\"quoted\\\" \\u2014\"").unwrap(),
+        (
+            "content".to_string(),
+            AttributeValue::String("line\n\"quoted\" \u{2014}".to_string())
+        )
+    );
+    assert_eq!(
+        parse_attribute_name("favorite_count: 12").unwrap(),
+        ("favorite_count".to_string(), AttributeValue::Integer(12))
+    );
+
+    for invalid in [
+        "no value",
+        ": 1",
+        "id: unquoted",
+        "id: \"unterminated",
+        "flag: true",
+        "ratio: 1.5",
+    ] {
+        assert!(
+            matches!(
+                parse_attribute_name(invalid),
+                Err(ApiError::InvalidMessage(_))
+            ),
+            "{} should be rejected",
+            invalid
+        );
+    }
+}
+
+#[test]
+fn test_parse_attributes_duplicate_keys() {
+    let duplicate = vec![attribute("id: \"1\""), attribute("id: \"1\"")];
+    assert!(matches!(
+        parse_attributes(&duplicate),
+        Err(ApiError::InvalidMessage(msg)) if msg.contains("duplicate")
+    ));
+
+    let conflicting = vec![attribute("id: \"1\""), attribute("id : \"2\"")];
+    assert!(matches!(
+        parse_attributes(&conflicting),
+        Err(ApiError::InvalidMessage(msg)) if msg.contains("conflicting")
+    ));
+    assert!(find_post_id_attribute(&conflicting).is_err());
+}
+
+#[test]
+fn test_find_typed_attributes() {
+    let attributes = vec![
+        attribute("bookmark_count: \"1.2K\""),
+        attribute("retweet_count: -1"),
+        attribute("favorite_count: \"lots\""),
+    ];
+
+    // 字符串形式的互动数按 Twitter 的缩写解析
+    assert_eq!(find_bookmark_count_attribute(&attributes).unwrap(), 1_200);
+    assert!(matches!(
+        find_retweet_count_attribute(&attributes),
+        Err(ApiError::InvalidMessage(_))
+    ));
+    assert!(matches!(
+        find_favorite_count_attribute(&attributes),
+        Err(ApiError::InvalidMessage(_))
+    ));
+    assert!(matches!(
+        find_favorite_count_attribute(&[]),
+        Err(ApiError::NotFound(_))
+    ));
 }
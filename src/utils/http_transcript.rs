@@ -0,0 +1,862 @@
+use std::fmt;
+use std::io::Read;
+
+use flate2::read::{DeflateDecoder, MultiGzDecoder, ZlibDecoder};
+use serde::{Deserialize, Serialize};
+
+use crate::error::ApiError;
+
+/// 解析 transcript 时的上限, 通过配置文件的 `[transcript]` 修改
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TranscriptLimits {
+    /// 单个报文允许的最大 header 数量
+    pub max_headers: usize,
+    /// 解压后 body 的最大长度, 防止压缩炸弹
+    pub max_decoded_body_bytes: u64,
+}
+
+impl Default for TranscriptLimits {
+    fn default() -> Self {
+        TranscriptLimits {
+            max_headers: 128,
+            max_decoded_body_bytes: 16 * 1024 * 1024,
+        }
+    }
+}
+
+impl TranscriptLimits {
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if self.max_headers == 0 || self.max_decoded_body_bytes == 0 {
+            return Err(ApiError::ConfigError(
+                "transcript.max_headers and transcript.max_decoded_body_bytes must be positive"
+                    .to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// notary 签名的 transcript: 先是发送的全部请求, 然后是收到的全部响应
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct HttpTranscript {
+    pub requests: Vec<HttpRequest>,
+    pub responses: Vec<HttpResponse>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpRequest {
+    pub method: String,
+    pub target: String,
+    /// HTTP/1.x 的小版本号
+    pub version: u8,
+    pub headers: HttpHeaders,
+    /// 去掉 chunked 编码后的 body
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpResponse {
+    /// HTTP/1.x 的小版本号
+    pub version: u8,
+    pub status: u16,
+    pub reason: String,
+    pub headers: HttpHeaders,
+    /// 去掉 chunked 编码并按 Content-Encoding 解压后的 body
+    pub body: Vec<u8>,
+}
+
+/// 按原始顺序保存的 header, 名称比较不区分大小写
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct HttpHeaders(pub Vec<(String, String)>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TranscriptError {
+    // transcript 中没有请求
+    MissingRequest,
+    // 请求没有对应的响应, 或响应多于请求
+    UnmatchedExchange { requests: usize, responses: usize },
+    // 请求/响应头不完整
+    IncompleteHead,
+    // 请求行或 header 不合法
+    InvalidHead(String),
+    // header 的值不是合法的 UTF-8
+    InvalidHeaderValue(String),
+    // Content-Length 不合法或出现多个不同的值
+    InvalidContentLength,
+    // body 比声明的长度短
+    IncompleteBody { expected: usize, actual: usize },
+    // chunked 编码不合法
+    InvalidChunk,
+    // 不支持的 Transfer-Encoding / Content-Encoding
+    UnsupportedEncoding(String),
+    // 解压失败
+    Decompression(String),
+}
+
+impl fmt::Display for TranscriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TranscriptError::MissingRequest => write!(f, "transcript contains no request"),
+            TranscriptError::UnmatchedExchange {
+                requests,
+                responses,
+            } => write!(
+                f,
+                "transcript contains {} requests but {} responses",
+                requests, responses
+            ),
+            TranscriptError::IncompleteHead => write!(f, "message head is incomplete"),
+            TranscriptError::InvalidHead(msg) => write!(f, "invalid message head: {}", msg),
+            TranscriptError::InvalidHeaderValue(name) => {
+                write!(f, "header {} is not valid UTF-8", name)
+            }
+            TranscriptError::InvalidContentLength => write!(f, "invalid Content-Length"),
+            TranscriptError::IncompleteBody { expected, actual } => write!(
+                f,
+                "body is incomplete: expected {} bytes, got {}",
+                expected, actual
+            ),
+            TranscriptError::InvalidChunk => write!(f, "invalid chunked body"),
+            TranscriptError::UnsupportedEncoding(encoding) => {
+                write!(f, "unsupported encoding: {}", encoding)
+            }
+            TranscriptError::Decompression(msg) => write!(f, "failed to decompress body: {}", msg),
+        }
+    }
+}
+
+impl From<TranscriptError> for ApiError {
+    fn from(err: TranscriptError) -> Self {
+        ApiError::InvalidTranscript(err.to_string())
+    }
+}
+
+impl HttpHeaders {
+    /// 返回第一个同名 header
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// 返回全部同名 header
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.0
+            .iter()
+            .filter(move |(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// 将逗号分隔的同名 header 展开为列表 (RFC 9110 §5.3)
+    fn get_list(&self, name: &str) -> Vec<String> {
+        self.get_all(name)
+            .flat_map(|value| value.split(','))
+            .map(|item| item.trim().to_ascii_lowercase())
+            .filter(|item| !item.is_empty())
+            .collect()
+    }
+}
+
+impl HttpRequest {
+    /// 请求的目标 host, 取自唯一的 Host header
+    ///
+    /// Host header 缺失或重复, 或与 absolute-form 的 authority 不一致时返回 `None`
+    pub fn host(&self) -> Option<&str> {
+        let mut hosts = self.headers.get_all("host");
+        let host = hosts.next().filter(|host| !host.is_empty())?;
+        if hosts.next().is_some() {
+            return None;
+        }
+
+        match self.target.split_once("://") {
+            Some((_, rest)) => {
+                let authority = rest.split(['/', '?', '#']).next().unwrap_or("");
+                authority.eq_ignore_ascii_case(host).then_some(host)
+            }
+            None => Some(host),
+        }
+    }
+}
+
+impl HttpTranscript {
+    /// 解析 hex 编码的 transcript
+    pub fn from_hex(hex_string: &str, limits: &TranscriptLimits) -> Result<Self, ApiError> {
+        let hex_string = hex_string.replace(char::is_whitespace, "");
+        let bytes = hex::decode(hex_string).map_err(|_| {
+            ApiError::InvalidTranscript("application data must be a valid hex string".to_string())
+        })?;
+        Ok(Self::parse(&bytes, limits)?)
+    }
+
+    pub fn parse(data: &[u8], limits: &TranscriptLimits) -> Result<Self, TranscriptError> {
+        let mut rest = data;
+
+        // pipelining: 所有请求在前, 直到遇到第一个状态行
+        let mut requests = Vec::new();
+        while !rest.is_empty() && !rest.starts_with(b"HTTP/") {
+            let (request, consumed) = parse_request(rest, limits)?;
+            requests.push(request);
+            rest = &rest[consumed..];
+        }
+        if requests.is_empty() {
+            return Err(TranscriptError::MissingRequest);
+        }
+
+        let mut responses = Vec::new();
+        while !rest.is_empty() {
+            let request =
+                requests
+                    .get(responses.len())
+                    .ok_or(TranscriptError::UnmatchedExchange {
+                        requests: requests.len(),
+                        responses: responses.len() + 1,
+                    })?;
+            let (response, consumed) = parse_response(rest, request, limits)?;
+            responses.push(response);
+            rest = &rest[consumed..];
+        }
+        if responses.len() != requests.len() {
+            return Err(TranscriptError::UnmatchedExchange {
+                requests: requests.len(),
+                responses: responses.len(),
+            });
+        }
+
+        Ok(HttpTranscript {
+            requests,
+            responses,
+        })
+    }
+}
+
+fn parse_request(
+    data: &[u8],
+    limits: &TranscriptLimits,
+) -> Result<(HttpRequest, usize), TranscriptError> {
+    let (head, head_len) = match parse_request_head(data, limits) {
+        // 浏览器插件记录的请求偶尔会把 cookie 折成多行, 只有这种情况拼回去再解析一次
+        Err(TranscriptError::InvalidHead(err)) => {
+            let head_len = find_head_end(data).ok_or(TranscriptError::InvalidHead(err.clone()))?;
+            let unfolded =
+                unfold_cookie_lines(&data[..head_len]).ok_or(TranscriptError::InvalidHead(err))?;
+            (parse_request_head(&unfolded, limits)?.0, head_len)
+        }
+        result => result?,
+    };
+    let RequestHead {
+        method,
+        target,
+        version,
+        headers,
+    } = head;
+
+    // 请求没有 Transfer-Encoding 和 Content-Length 时 body 为空 (RFC 9112 §6.3)
+    let (body, body_len) = match body_framing(&headers)? {
+        BodyFraming::Chunked => decode_chunked(&data[head_len..])?,
+        BodyFraming::Length(len) => take_exact(&data[head_len..], len)?,
+        BodyFraming::UntilClose | BodyFraming::None => (Vec::new(), 0),
+    };
+
+    Ok((
+        HttpRequest {
+            method,
+            target,
+            version,
+            headers,
+            body,
+        },
+        head_len + body_len,
+    ))
+}
+
+/// 请求行与 header
+struct RequestHead {
+    method: String,
+    target: String,
+    version: u8,
+    headers: HttpHeaders,
+}
+
+/// 严格解析请求头, 不合法的 header 行直接报错; 返回请求头以及它的长度
+fn parse_request_head(
+    data: &[u8],
+    limits: &TranscriptLimits,
+) -> Result<(RequestHead, usize), TranscriptError> {
+    let mut raw_headers = vec![httparse::EMPTY_HEADER; limits.max_headers];
+    let mut request = httparse::Request::new(&mut raw_headers);
+    let head_len = match request.parse(data) {
+        Ok(httparse::Status::Complete(len)) => len,
+        Ok(httparse::Status::Partial) => return Err(TranscriptError::IncompleteHead),
+        Err(err) => return Err(TranscriptError::InvalidHead(err.to_string())),
+    };
+
+    let head = RequestHead {
+        method: request.method.unwrap_or_default().to_string(),
+        target: request.path.unwrap_or_default().to_string(),
+        version: request.version.unwrap_or_default(),
+        headers: collect_headers(request.headers)?,
+    };
+    Ok((head, head_len))
+}
+
+/// 请求头结尾 (含空行) 的位置
+fn find_head_end(data: &[u8]) -> Option<usize> {
+    data.windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .map(|pos| pos + 4)
+}
+
+/// 把紧跟在 `cookie` header 之后、不是合法 header 的续行拼回 cookie 的值
+///
+/// 没有这样的续行时返回 `None`; 其他位置的非法行保持原样, 再次解析时依然报错
+fn unfold_cookie_lines(head: &[u8]) -> Option<Vec<u8>> {
+    let mut lines: Vec<Vec<u8>> = Vec::new();
+    let mut in_cookie = false;
+    let mut unfolded = false;
+
+    let body = head.strip_suffix(b"\r\n\r\n")?;
+    for (index, line) in body.split(|&byte| byte == b'\n').enumerate() {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if index == 0 {
+            lines.push(line.to_vec());
+            continue;
+        }
+        match header_name(line) {
+            Some(name) => {
+                in_cookie = name.eq_ignore_ascii_case(b"cookie");
+                lines.push(line.to_vec());
+            }
+            None if in_cookie && !line.is_empty() => {
+                let cookie = lines
+                    .last_mut()
+                    .expect("cookie line precedes its continuation");
+                cookie.push(b' ');
+                cookie.extend_from_slice(line);
+                unfolded = true;
+            }
+            None => {
+                in_cookie = false;
+                lines.push(line.to_vec());
+            }
+        }
+    }
+    if !unfolded {
+        return None;
+    }
+
+    let mut unfolded_head = lines.join(&b"\r\n"[..]);
+    unfolded_head.extend_from_slice(b"\r\n\r\n");
+    Some(unfolded_head)
+}
+
+/// 合法 header 行 (`token ":" value`) 的名字
+fn header_name(line: &[u8]) -> Option<&[u8]> {
+    let colon = line.iter().position(|&byte| byte == b':')?;
+    let name = &line[..colon];
+    let is_tchar = |byte: &u8| byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(byte);
+    (!name.is_empty() && name.iter().all(is_tchar)).then_some(name)
+}
+
+fn parse_response(
+    data: &[u8],
+    request: &HttpRequest,
+    limits: &TranscriptLimits,
+) -> Result<(HttpResponse, usize), TranscriptError> {
+    let mut raw_headers = vec![httparse::EMPTY_HEADER; limits.max_headers];
+    let mut response = httparse::Response::new(&mut raw_headers);
+    let head_len = match response.parse(data) {
+        Ok(httparse::Status::Complete(len)) => len,
+        Ok(httparse::Status::Partial) => return Err(TranscriptError::IncompleteHead),
+        Err(err) => return Err(TranscriptError::InvalidHead(err.to_string())),
+    };
+
+    let version = response.version.unwrap_or_default();
+    let status = response.code.unwrap_or_default();
+    let reason = response.reason.unwrap_or_default().to_string();
+    let headers = collect_headers(response.headers)?;
+
+    // HEAD 请求以及 1xx/204/304 响应没有 body (RFC 9112 §6.3)
+    let has_body = !request.method.eq_ignore_ascii_case("HEAD")
+        && !(100..200).contains(&status)
+        && status != 204
+        && status != 304;
+    let framing = if has_body {
+        body_framing(&headers)?
+    } else {
+        BodyFraming::None
+    };
+
+    let rest = &data[head_len..];
+    let (body, body_len) = match framing {
+        BodyFraming::Chunked => decode_chunked(rest)?,
+        BodyFraming::Length(len) => take_exact(rest, len)?,
+        BodyFraming::UntilClose => (rest.to_vec(), rest.len()),
+        BodyFraming::None => (Vec::new(), 0),
+    };
+    let body = decode_content(
+        body,
+        &headers.get_list("content-encoding"),
+        limits.max_decoded_body_bytes,
+    )?;
+
+    Ok((
+        HttpResponse {
+            version,
+            status,
+            reason,
+            headers,
+            body,
+        },
+        head_len + body_len,
+    ))
+}
+
+fn collect_headers(raw_headers: &[httparse::Header<'_>]) -> Result<HttpHeaders, TranscriptError> {
+    raw_headers
+        .iter()
+        .map(|header| {
+            let value = std::str::from_utf8(header.value)
+                .map_err(|_| TranscriptError::InvalidHeaderValue(header.name.to_string()))?;
+            Ok((header.name.to_string(), value.trim().to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(HttpHeaders)
+}
+
+enum BodyFraming {
+    Chunked,
+    Length(usize),
+    UntilClose,
+    None,
+}
+
+/// 按 RFC 9112 §6.3 确定 body 的边界
+fn body_framing(headers: &HttpHeaders) -> Result<BodyFraming, TranscriptError> {
+    let transfer_encodings = headers.get_list("transfer-encoding");
+    if let Some(last) = transfer_encodings.last() {
+        if let Some(encoding) = transfer_encodings
+            .iter()
+            .find(|encoding| *encoding != "chunked")
+        {
+            return Err(TranscriptError::UnsupportedEncoding(encoding.clone()));
+        }
+        // Transfer-Encoding 优先于 Content-Length
+        return Ok(if last == "chunked" {
+            BodyFraming::Chunked
+        } else {
+            BodyFraming::UntilClose
+        });
+    }
+
+    let mut content_length = None;
+    for value in headers.get_list("content-length") {
+        let len = value
+            .parse::<usize>()
+            .map_err(|_| TranscriptError::InvalidContentLength)?;
+        if content_length.is_some_and(|existing| existing != len) {
+            return Err(TranscriptError::InvalidContentLength);
+        }
+        content_length = Some(len);
+    }
+
+    Ok(match content_length {
+        Some(len) => BodyFraming::Length(len),
+        None => BodyFraming::UntilClose,
+    })
+}
+
+fn take_exact(data: &[u8], len: usize) -> Result<(Vec<u8>, usize), TranscriptError> {
+    if data.len() < len {
+        return Err(TranscriptError::IncompleteBody {
+            expected: len,
+            actual: data.len(),
+        });
+    }
+    Ok((data[..len].to_vec(), len))
+}
+
+/// 解码 chunked body, 返回 body 和消耗的字节数
+fn decode_chunked(data: &[u8]) -> Result<(Vec<u8>, usize), TranscriptError> {
+    let mut body = Vec::new();
+    let mut pos = 0;
+
+    loop {
+        let line = read_line(data, pos)?;
+        pos += line.len() + 2;
+
+        // chunk-size [ ; chunk-ext ]
+        let size = std::str::from_utf8(line)
+            .ok()
+            .and_then(|line| line.split(';').next())
+            .and_then(|size| usize::from_str_radix(size.trim(), 16).ok())
+            .ok_or(TranscriptError::InvalidChunk)?;
+
+        if size == 0 {
+            break;
+        }
+
+        // chunk 大小由对端控制, 所有偏移都需要检查溢出
+        let end = pos.checked_add(size).ok_or(TranscriptError::InvalidChunk)?;
+        let chunk_end = end.checked_add(2).ok_or(TranscriptError::InvalidChunk)?;
+        if data.get(end..chunk_end) != Some(b"\r\n".as_slice()) {
+            return Err(TranscriptError::InvalidChunk);
+        }
+        body.extend_from_slice(&data[pos..end]);
+        pos = chunk_end;
+    }
+
+    // trailer 以空行结束
+    loop {
+        let line = read_line(data, pos)?;
+        pos += line.len() + 2;
+        if line.is_empty() {
+            break;
+        }
+    }
+
+    Ok((body, pos))
+}
+
+fn read_line(data: &[u8], start: usize) -> Result<&[u8], TranscriptError> {
+    let rest = data.get(start..).ok_or(TranscriptError::InvalidChunk)?;
+    rest.windows(2)
+        .position(|window| window == b"\r\n")
+        .map(|end| &rest[..end])
+        .ok_or(TranscriptError::InvalidChunk)
+}
+
+/// 按 Content-Encoding 的逆序解压 body
+fn decode_content(
+    mut body: Vec<u8>,
+    encodings: &[String],
+    max_len: u64,
+) -> Result<Vec<u8>, TranscriptError> {
+    for encoding in encodings.iter().rev() {
+        body = match encoding.as_str() {
+            "identity" => body,
+            "gzip" | "x-gzip" => read_limited(MultiGzDecoder::new(body.as_slice()), max_len)?,
+            // 部分服务器的 deflate 不带 zlib 头
+            "deflate" => read_limited(ZlibDecoder::new(body.as_slice()), max_len)
+                .or_else(|_| read_limited(DeflateDecoder::new(body.as_slice()), max_len))?,
+            "br" => read_limited(brotli::Decompressor::new(body.as_slice(), 4096), max_len)?,
+            other => return Err(TranscriptError::UnsupportedEncoding(other.to_string())),
+        };
+    }
+    Ok(body)
+}
+
+fn read_limited(reader: impl Read, max_len: u64) -> Result<Vec<u8>, TranscriptError> {
+    let mut decoded = Vec::new();
+    reader
+        .take(max_len + 1)
+        .read_to_end(&mut decoded)
+        .map_err(|err| TranscriptError::Decompression(err.to_string()))?;
+    if decoded.len() as u64 > max_len {
+        return Err(TranscriptError::Decompression(
+            "decoded body is too large".to_string(),
+        ));
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::{DeflateEncoder, GzEncoder, ZlibEncoder};
+    use flate2::Compression;
+
+    use super::*;
+
+    fn parse(data: &[u8]) -> Result<HttpTranscript, TranscriptError> {
+        HttpTranscript::parse(data, &TranscriptLimits::default())
+    }
+
+    fn exchange(response_head: &str, body: &[u8]) -> Vec<u8> {
+        let mut data = b"GET /api HTTP/1.1\r\nhost: x.com\r\n\r\n".to_vec();
+        data.extend_from_slice(response_head.as_bytes());
+        data.extend_from_slice(body);
+        data
+    }
+
+    #[test]
+    fn test_parse_tweet_detail_fixture() {
+        let transcript = HttpTranscript::from_hex(
+            include_str!("fixtures/tweet_detail.hex"),
+            &TranscriptLimits::default(),
+        )
+        .unwrap();
+
+        assert_eq!(transcript.requests.len(), 1);
+        let request = &transcript.requests[0];
+        assert_eq!(request.method, "GET");
+        // 记录中被折行的 cookie 拼回了一个 header
+        assert!(request
+            .headers
+            .get("cookie")
+            .unwrap()
+            .contains("twid=u%3D1248668065148973061"));
+        assert_eq!(request.host(), Some("x.com"));
+        assert_eq!(request.headers.get("Host"), Some("x.com"));
+        assert!(request.target.contains("/TweetDetail?"));
+
+        let response = &transcript.responses[0];
+        assert_eq!(response.status, 200);
+        assert_eq!(response.reason, "OK");
+        assert_eq!(
+            response.headers.get("content-type"),
+            Some("application/json; charset=utf-8")
+        );
+        let body = std::str::from_utf8(&response.body).unwrap();
+        assert!(body.contains("pocket—you never know"));
+    }
+
+    #[test]
+    fn test_parse_folded_request_header() {
+        let data = b"GET /a HTTP/1.1\r\ncookie: a=1;\r\nb=2\r\nHost: x.com\r\n\r\n\
+HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n";
+        let transcript = parse(data).unwrap();
+        assert_eq!(
+            transcript.requests[0].headers.get("cookie"),
+            Some("a=1; b=2")
+        );
+        assert_eq!(transcript.requests[0].host(), Some("x.com"));
+
+        // 续行中带有冒号也属于 cookie
+        let data = b"GET /a HTTP/1.1\r\nCookie: a=1;\r\nb=\"x:y\"\r\nHost: x.com\r\n\r\n\
+HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n";
+        let transcript = parse(data).unwrap();
+        assert_eq!(
+            transcript.requests[0].headers.get("cookie"),
+            Some("a=1; b=\"x:y\"")
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_request_header() {
+        let cases: [&[u8]; 3] = [
+            // 不在 cookie 之后的非法行
+            b"GET /a HTTP/1.1\r\nHost: x.com\r\nb=2\r\n\r\n",
+            b"GET /a HTTP/1.1\r\nb=2\r\nHost: x.com\r\n\r\n",
+            // 非法的 header 名
+            b"GET /a HTTP/1.1\r\nHo st: evil.com\r\nHost: x.com\r\n\r\n",
+        ];
+        for head in cases {
+            let mut data = head.to_vec();
+            data.extend_from_slice(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n");
+            assert!(
+                matches!(parse(&data), Err(TranscriptError::InvalidHead(_))),
+                "{:?} should be rejected",
+                String::from_utf8_lossy(head)
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_chunked_with_trailer() {
+        let data = exchange(
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n",
+            b"5;ext=1\r\nhello\r\n7\r\n, world\r\n0\r\nx-trailer: 1\r\n\r\n",
+        );
+        let transcript = parse(&data).unwrap();
+        assert_eq!(transcript.responses[0].body, b"hello, world");
+    }
+
+    #[test]
+    fn test_parse_invalid_chunk() {
+        let data = exchange(
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n",
+            b"zz\r\nhello\r\n0\r\n\r\n",
+        );
+        assert_eq!(parse(&data).unwrap_err(), TranscriptError::InvalidChunk);
+
+        // chunk 结束位置恰好是 usize::MAX - 1, 加上 CRLF 时不能溢出
+        let data = exchange(
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n",
+            b"ffffffffffffffec\r\nhello\r\n0\r\n\r\n",
+        );
+        assert_eq!(parse(&data).unwrap_err(), TranscriptError::InvalidChunk);
+    }
+
+    #[test]
+    fn test_parse_content_length() {
+        let data = exchange("HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n", b"hello");
+        let transcript = parse(&data).unwrap();
+        assert_eq!(transcript.responses[0].body, b"hello");
+
+        let data = exchange("HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n", b"hello");
+        assert_eq!(
+            parse(&data).unwrap_err(),
+            TranscriptError::IncompleteBody {
+                expected: 10,
+                actual: 5
+            }
+        );
+
+        let data = exchange(
+            "HTTP/1.1 200 OK\r\nContent-Length: 5\r\nContent-Length: 6\r\n\r\n",
+            b"hello!",
+        );
+        assert_eq!(
+            parse(&data).unwrap_err(),
+            TranscriptError::InvalidContentLength
+        );
+    }
+
+    #[test]
+    fn test_parse_repeated_headers() {
+        let data = exchange(
+            "HTTP/1.1 200 OK\r\nSet-Cookie: a=1\r\nset-cookie: b=2\r\nContent-Length: 0\r\n\r\n",
+            b"",
+        );
+        let transcript = parse(&data).unwrap();
+        let cookies: Vec<&str> = transcript.responses[0]
+            .headers
+            .get_all("Set-Cookie")
+            .collect();
+        assert_eq!(cookies, vec!["a=1", "b=2"]);
+    }
+
+    #[test]
+    fn test_parse_compressed_bodies() {
+        let json = "{\"full_text\":\"中文 — ok\"}".as_bytes();
+
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(json).unwrap();
+        let mut zlib = ZlibEncoder::new(Vec::new(), Compression::default());
+        zlib.write_all(json).unwrap();
+        let mut raw_deflate = DeflateEncoder::new(Vec::new(), Compression::default());
+        raw_deflate.write_all(json).unwrap();
+        let mut brotli = Vec::new();
+        {
+            let mut writer = brotli::CompressorWriter::new(&mut brotli, 4096, 5, 22);
+            writer.write_all(json).unwrap();
+        }
+
+        for (encoding, body) in [
+            ("gzip", gzip.finish().unwrap()),
+            ("deflate", zlib.finish().unwrap()),
+            ("deflate", raw_deflate.finish().unwrap()),
+            ("br", brotli),
+        ] {
+            let head = format!(
+                "HTTP/1.1 200 OK\r\nContent-Encoding: {}\r\nContent-Length: {}\r\n\r\n",
+                encoding,
+                body.len()
+            );
+            let transcript = parse(&exchange(&head, &body)).unwrap();
+            assert_eq!(transcript.responses[0].body, json, "{}", encoding);
+        }
+
+        let data = exchange(
+            "HTTP/1.1 200 OK\r\nContent-Encoding: zstd\r\nContent-Length: 1\r\n\r\n",
+            b"x",
+        );
+        assert_eq!(
+            parse(&data).unwrap_err(),
+            TranscriptError::UnsupportedEncoding("zstd".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(&[b'a'; 1024]).unwrap();
+        let body = gzip.finish().unwrap();
+        let data = exchange(
+            &format!(
+                "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\n\r\n",
+                body.len()
+            ),
+            &body,
+        );
+        assert!(parse(&data).is_ok());
+
+        // 解压后超过上限
+        let limits = TranscriptLimits {
+            max_decoded_body_bytes: 1023,
+            ..TranscriptLimits::default()
+        };
+        assert!(matches!(
+            HttpTranscript::parse(&data, &limits),
+            Err(TranscriptError::Decompression(_))
+        ));
+
+        // 响应有 2 个 header
+        let limits = TranscriptLimits {
+            max_headers: 1,
+            ..TranscriptLimits::default()
+        };
+        assert!(matches!(
+            HttpTranscript::parse(&data, &limits),
+            Err(TranscriptError::InvalidHead(_))
+        ));
+
+        assert!(TranscriptLimits::default().validate().is_ok());
+        assert!(matches!(
+            TranscriptLimits {
+                max_headers: 0,
+                ..TranscriptLimits::default()
+            }
+            .validate(),
+            Err(ApiError::ConfigError(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_pipelined_requests() {
+        let data = b"POST /a HTTP/1.1\r\nHost: x.com\r\nContent-Length: 2\r\n\r\n{}\
+GET /b HTTP/1.1\r\nHost: x.com\r\n\r\n\
+HTTP/1.1 200 OK\r\nContent-Length: 1\r\n\r\na\
+HTTP/1.1 404 Not Found\r\nContent-Length: 1\r\n\r\nb";
+        let transcript = parse(data).unwrap();
+
+        assert_eq!(transcript.requests.len(), 2);
+        assert_eq!(transcript.requests[0].body, b"{}");
+        assert_eq!(transcript.requests[1].target, "/b");
+        assert_eq!(transcript.responses[0].body, b"a");
+        assert_eq!(transcript.responses[1].status, 404);
+        assert_eq!(transcript.responses[1].body, b"b");
+    }
+
+    #[test]
+    fn test_parse_unmatched_exchange() {
+        let data = b"GET /a HTTP/1.1\r\nHost: x.com\r\n\r\nGET /b HTTP/1.1\r\nHost: x.com\r\n\r\n\
+HTTP/1.1 200 OK\r\nContent-Length: 1\r\n\r\na";
+        assert_eq!(
+            parse(data).unwrap_err(),
+            TranscriptError::UnmatchedExchange {
+                requests: 2,
+                responses: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_head_and_no_content() {
+        let data = b"HEAD /a HTTP/1.1\r\nHost: x.com\r\n\r\nGET /b HTTP/1.1\r\nHost: x.com\r\n\r\n\
+HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\n\
+HTTP/1.1 204 No Content\r\n\r\n";
+        let transcript = parse(data).unwrap();
+        assert!(transcript.responses[0].body.is_empty());
+        assert!(transcript.responses[1].body.is_empty());
+    }
+
+    #[test]
+    fn test_parse_invalid_input() {
+        assert!(matches!(
+            HttpTranscript::from_hex("zz", &TranscriptLimits::default()),
+            Err(ApiError::InvalidTranscript(_))
+        ));
+        assert_eq!(
+            parse(b"GET /a HTTP/1.1\r\nHost: x.com\r\n").unwrap_err(),
+            TranscriptError::IncompleteHead
+        );
+        assert_eq!(
+            parse(b"HTTP/1.1 200 OK\r\n\r\n").unwrap_err(),
+            TranscriptError::MissingRequest
+        );
+    }
+}
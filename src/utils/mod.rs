@@ -1,12 +1,24 @@
+pub mod engagement_count;
+pub use engagement_count::*;
+
 pub mod find_spec_attribute;
 pub use find_spec_attribute::*;
 
-// pub mod decode_app_data;
-// pub use decode_app_data::*;
+pub mod http_transcript;
+
+pub mod decode_app_data;
 
 pub mod deserialize_message;
 pub use deserialize_message::*;
 
+pub mod notary_trust_store;
+
+pub mod redeem_store;
+
+pub mod clock;
+
+pub mod admin_token;
+
 pub mod verify_signature;
 // pub use verify_signature::*;
 
@@ -0,0 +1,284 @@
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use p256::{ecdsa::VerifyingKey, pkcs8::DecodePublicKey};
+use serde::Deserialize;
+
+use crate::error::ApiError;
+
+/// 信任的 notary 列表, 根据 session 中声明的 notaryUrl 选择验证公钥
+#[derive(Debug)]
+pub struct NotaryTrustStore {
+    notaries: Vec<TrustedNotary>,
+}
+
+/// 一个 notary 以及它的全部公钥
+#[derive(Debug)]
+pub struct TrustedNotary {
+    /// notary 的标识
+    pub id: String,
+    /// notary 对外的 url, 与 session 中的 notaryUrl 对应
+    pub urls: Vec<String>,
+    /// notary 的公钥, 轮换期间可以同时存在多个
+    pub keys: Vec<NotaryKey>,
+}
+
+/// notary 的一个公钥及其有效期 (unix 秒)
+#[derive(Debug)]
+pub struct NotaryKey {
+    pub verifying_key: VerifyingKey,
+    pub not_before: Option<u64>,
+    pub not_after: Option<u64>,
+    /// 该公钥对自检向量的签名, 启动和就绪检查时逐个验证
+    pub self_check_signature: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TrustStoreFile {
+    notaries: Vec<TrustedNotaryFile>,
+}
+
+#[derive(Deserialize)]
+struct TrustedNotaryFile {
+    id: String,
+    #[serde(default)]
+    urls: Vec<String>,
+    keys: Vec<NotaryKeyFile>,
+}
+
+#[derive(Deserialize)]
+struct NotaryKeyFile {
+    public_key_pem: String,
+    not_before: Option<u64>,
+    not_after: Option<u64>,
+    #[serde(default)]
+    self_check_signature: Option<String>,
+}
+
+impl NotaryKey {
+    pub fn is_valid_at(&self, now: u64) -> bool {
+        self.not_before.is_none_or(|not_before| now >= not_before)
+            && self.not_after.is_none_or(|not_after| now < not_after)
+    }
+}
+
+impl TrustedNotary {
+    /// 只按 url 匹配, id 只是配置中的名字
+    fn matches(&self, notary: &str) -> bool {
+        let notary = normalize_notary_url(notary);
+        self.urls
+            .iter()
+            .any(|url| normalize_notary_url(url) == notary)
+    }
+}
+
+impl NotaryTrustStore {
+    /// 示例信任列表, notary 的 url 是占位符, 只用于测试
+    #[cfg(test)]
+    pub fn example() -> Result<Self, ApiError> {
+        Self::from_json(include_str!("../notary/trust_store.example.json"))
+    }
+
+    /// 从 `notary.trust_store_path` 读取, notary 的 url 因部署而异, 没有内置的默认值
+    pub fn load(path: &Path) -> Result<Self, ApiError> {
+        let json = std::fs::read_to_string(path).map_err(|err| {
+            ApiError::ConfigError(format!(
+                "Cannot read notary trust store {}: {}",
+                path.display(),
+                err
+            ))
+        })?;
+        Self::from_json(&json)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, ApiError> {
+        let file: TrustStoreFile = serde_json::from_str(json).map_err(|err| {
+            ApiError::UntrustedNotary(format!("Invalid notary trust store: {}", err))
+        })?;
+
+        let mut notaries = Vec::with_capacity(file.notaries.len());
+        for notary in file.notaries {
+            // 没有 url 的 notary 永远匹配不到 session 的 notaryUrl
+            if notary.urls.is_empty() {
+                return Err(ApiError::UntrustedNotary(format!(
+                    "Notary {} must list at least one url",
+                    notary.id
+                )));
+            }
+            let mut keys = Vec::with_capacity(notary.keys.len());
+            for key in notary.keys {
+                let verifying_key = VerifyingKey::from_public_key_pem(&key.public_key_pem)
+                    .map_err(|_| {
+                        ApiError::UntrustedNotary(format!(
+                            "Notary {} has a key that is not a valid P-256 PEM",
+                            notary.id
+                        ))
+                    })?;
+                keys.push(NotaryKey {
+                    verifying_key,
+                    not_before: key.not_before,
+                    not_after: key.not_after,
+                    self_check_signature: key.self_check_signature,
+                });
+            }
+            notaries.push(TrustedNotary {
+                id: notary.id,
+                urls: notary.urls,
+                keys,
+            });
+        }
+
+        Ok(NotaryTrustStore { notaries })
+    }
+
+    pub fn notaries(&self) -> &[TrustedNotary] {
+        &self.notaries
+    }
+
+    /// 返回 session 声明的 notary 在 `now` 时刻有效的全部公钥
+    pub fn resolve(&self, notary_url: &str, now: u64) -> Result<Vec<VerifyingKey>, ApiError> {
+        let notary = self
+            .notaries
+            .iter()
+            .find(|notary| notary.matches(notary_url))
+            .ok_or_else(|| {
+                ApiError::UntrustedNotary(format!("Notary {} is not trusted", notary_url))
+            })?;
+
+        let keys: Vec<VerifyingKey> = notary
+            .keys
+            .iter()
+            .filter(|key| key.is_valid_at(now))
+            .map(|key| key.verifying_key)
+            .collect();
+
+        if keys.is_empty() {
+            return Err(ApiError::UntrustedNotary(format!(
+                "Notary {} has no key valid at this time",
+                notary.id
+            )));
+        }
+
+        Ok(keys)
+    }
+}
+
+/// 当前的 unix 时间 (秒)
+pub fn now_unix() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+/// 忽略大小写、首尾空白和末尾的 `/`
+fn normalize_notary_url(url: &str) -> String {
+    url.trim().trim_end_matches('/').to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use p256::{ecdsa::SigningKey, pkcs8::EncodePublicKey};
+    use rand_core::OsRng;
+
+    use super::*;
+
+    fn public_key_pem(signing_key: &SigningKey) -> String {
+        VerifyingKey::from(signing_key)
+            .to_public_key_pem(Default::default())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_example_trust_store() {
+        let store = NotaryTrustStore::example().unwrap();
+        assert!(!store.notaries().is_empty());
+        assert!(store
+            .resolve("https://notary.example.com", now_unix())
+            .is_ok());
+
+        // notary 的 id 不能代替 url
+        assert!(matches!(
+            store.resolve("esper", now_unix()),
+            Err(ApiError::UntrustedNotary(_))
+        ));
+    }
+
+    #[test]
+    fn test_notary_without_urls() {
+        let signing_key = SigningKey::random(&mut OsRng);
+        let json = serde_json::json!({
+            "notaries": [{
+                "id": "notary-a",
+                "urls": [],
+                "keys": [{ "public_key_pem": public_key_pem(&signing_key), "not_before": null, "not_after": null }]
+            }]
+        })
+        .to_string();
+
+        assert!(matches!(
+            NotaryTrustStore::from_json(&json),
+            Err(ApiError::UntrustedNotary(_))
+        ));
+    }
+
+    #[test]
+    fn test_resolve_by_url_and_validity_window() {
+        let old_key = SigningKey::random(&mut OsRng);
+        let new_key = SigningKey::random(&mut OsRng);
+        let json = serde_json::json!({
+            "notaries": [{
+                "id": "notary-a",
+                "urls": ["https://notary-a.example.com"],
+                "keys": [
+                    { "public_key_pem": public_key_pem(&old_key), "not_before": null, "not_after": 2000 },
+                    { "public_key_pem": public_key_pem(&new_key), "not_before": 1000, "not_after": null }
+                ]
+            }]
+        })
+        .to_string();
+        let store = NotaryTrustStore::from_json(&json).unwrap();
+
+        // 只有旧公钥有效
+        let keys = store.resolve("https://notary-a.example.com/", 500).unwrap();
+        assert_eq!(keys, vec![VerifyingKey::from(&old_key)]);
+
+        // 轮换期间两个公钥都有效
+        let keys = store.resolve("HTTPS://NOTARY-A.EXAMPLE.COM", 1500).unwrap();
+        assert_eq!(keys.len(), 2);
+
+        // 旧公钥过期
+        let keys = store.resolve("https://notary-a.example.com", 2500).unwrap();
+        assert_eq!(keys, vec![VerifyingKey::from(&new_key)]);
+    }
+
+    #[test]
+    fn test_resolve_unknown_notary() {
+        let store = NotaryTrustStore::example().unwrap();
+        assert!(matches!(
+            store.resolve("https://evil-notary.example.com", now_unix()),
+            Err(ApiError::UntrustedNotary(_))
+        ));
+    }
+
+    #[test]
+    fn test_resolve_without_valid_key() {
+        let signing_key = SigningKey::random(&mut OsRng);
+        let json = serde_json::json!({
+            "notaries": [{
+                "id": "notary-a",
+                "urls": ["https://notary-a.example.com"],
+                "keys": [
+                    { "public_key_pem": public_key_pem(&signing_key), "not_before": null, "not_after": 1000 }
+                ]
+            }]
+        })
+        .to_string();
+        let store = NotaryTrustStore::from_json(&json).unwrap();
+
+        assert!(matches!(
+            store.resolve("https://notary-a.example.com", 1000),
+            Err(ApiError::UntrustedNotary(_))
+        ));
+    }
+}
@@ -0,0 +1,393 @@
+use std::fmt;
+use std::path::Path;
+
+use redb::{Database, ReadableTable, ReadableTableMetadata, TableDefinition};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::error::ApiError;
+
+/// key 为 `<client>/<post_id>`, value 为 JSON 编码的 [`IssuedRedeemCode`]
+const ISSUED_CODES: TableDefinition<&str, &[u8]> = TableDefinition::new("issued_codes");
+/// 就绪检查写入的表, 只保存最后一次检查的时间
+const HEALTH_CHECK: TableDefinition<&str, u64> = TableDefinition::new("health_check");
+
+/// 已签发的兑换码
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct IssuedRedeemCode {
+    pub client: String,
+    pub post_id: String,
+    pub redeemcode: String,
+    pub signature: String,
+    pub policy_id: String,
+    /// 签名公钥的 key ID
+    #[serde(default)]
+    pub key_id: String,
+    /// 签发时间 (unix 秒)
+    pub issued_at: u64,
+    /// 作废时间, 作废的兑换码不能再兑换
+    #[serde(default)]
+    pub revoked_at: Option<u64>,
+    /// 兑换时间
+    #[serde(default)]
+    pub consumed_at: Option<u64>,
+}
+
+/// 同一个推文重复提交时的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayPolicy {
+    /// 返回第一次签发的兑换码
+    #[default]
+    ReturnExisting,
+    /// 返回 AlreadyRedeemed 错误
+    Reject,
+}
+
+impl std::str::FromStr for ReplayPolicy {
+    type Err = ApiError;
+
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        match policy {
+            "return_existing" => Ok(ReplayPolicy::ReturnExisting),
+            "reject" => Ok(ReplayPolicy::Reject),
+            _ => Err(ApiError::ConfigError(format!(
+                "Unknown replay policy {}",
+                policy
+            ))),
+        }
+    }
+}
+
+/// 基于 redb 的兑换码存储, 每个 `(client, post_id)` 只签发一次
+pub struct RedeemStore {
+    db: Database,
+    policy: ReplayPolicy,
+}
+
+fn store_error(err: impl fmt::Display) -> ApiError {
+    ApiError::StoreError(format!("Redeem store error: {}", err))
+}
+
+fn store_key(client: &str, post_id: &str) -> String {
+    format!("{}/{}", client, post_id)
+}
+
+impl RedeemStore {
+    /// 打开或创建数据库文件
+    pub fn open(path: impl AsRef<Path>, policy: ReplayPolicy) -> Result<Self, ApiError> {
+        let db = Database::create(path).map_err(store_error)?;
+        Self::with_database(db, policy)
+    }
+
+    /// 不落盘的存储, 用于测试
+    #[cfg(test)]
+    pub fn in_memory(policy: ReplayPolicy) -> Result<Self, ApiError> {
+        let db = Database::builder()
+            .create_with_backend(redb::backends::InMemoryBackend::new())
+            .map_err(store_error)?;
+        Self::with_database(db, policy)
+    }
+
+    fn with_database(db: Database, policy: ReplayPolicy) -> Result<Self, ApiError> {
+        // 提前建表, 之后的只读事务才能打开
+        let txn = db.begin_write().map_err(store_error)?;
+        txn.open_table(ISSUED_CODES).map_err(store_error)?;
+        txn.commit().map_err(store_error)?;
+
+        Ok(RedeemStore { db, policy })
+    }
+
+    pub fn get(&self, client: &str, post_id: &str) -> Result<Option<IssuedRedeemCode>, ApiError> {
+        let txn = self.db.begin_read().map_err(store_error)?;
+        let table = txn.open_table(ISSUED_CODES).map_err(store_error)?;
+        let issued = table
+            .get(store_key(client, post_id).as_str())
+            .map_err(store_error)?;
+
+        issued
+            .map(|issued| serde_json::from_slice(issued.value()).map_err(store_error))
+            .transpose()
+    }
+
+    /// 提交一次写事务, 确认数据库仍然可写
+    pub fn check_writable(&self, now: u64) -> Result<(), ApiError> {
+        let txn = self.db.begin_write().map_err(store_error)?;
+        {
+            let mut table = txn.open_table(HEALTH_CHECK).map_err(store_error)?;
+            table.insert("last_check", now).map_err(store_error)?;
+        }
+        txn.commit().map_err(store_error)
+    }
+
+    /// 已保存的兑换码数量
+    pub fn len(&self) -> Result<u64, ApiError> {
+        let txn = self.db.begin_read().map_err(store_error)?;
+        let table = txn.open_table(ISSUED_CODES).map_err(store_error)?;
+        table.len().map_err(store_error)
+    }
+
+    /// 保存新签发的兑换码; 该推文已经签发过时按 [`ReplayPolicy`] 返回原来的兑换码或报错
+    ///
+    /// 查询与写入在同一个写事务中完成, 并发的重复提交也只会保存一个兑换码.
+    /// 第二个返回值表示这次是否保存了新的兑换码
+    pub fn issue(&self, issued: IssuedRedeemCode) -> Result<(IssuedRedeemCode, bool), ApiError> {
+        let key = store_key(&issued.client, &issued.post_id);
+        let txn = self.db.begin_write().map_err(store_error)?;
+
+        let existing = {
+            let mut table = txn.open_table(ISSUED_CODES).map_err(store_error)?;
+            let existing = table
+                .get(key.as_str())
+                .map_err(store_error)?
+                .map(|existing| serde_json::from_slice(existing.value()))
+                .transpose()
+                .map_err(store_error)?;
+
+            if existing.is_none() {
+                let value = serde_json::to_vec(&issued).map_err(store_error)?;
+                table
+                    .insert(key.as_str(), value.as_slice())
+                    .map_err(store_error)?;
+            }
+            existing
+        };
+        txn.commit().map_err(store_error)?;
+
+        match (existing, self.policy) {
+            (None, _) => Ok((issued, true)),
+            (Some(existing), ReplayPolicy::ReturnExisting) => Ok((existing, false)),
+            (Some(_), ReplayPolicy::Reject) => Err(ApiError::AlreadyRedeemed(format!(
+                "A redeem code has already been issued for post {}",
+                issued.post_id
+            ))),
+        }
+    }
+
+    /// 兑换兑换码, 已兑换或已作废时报错
+    ///
+    /// 检查与写入在同一个写事务中完成, 同一个兑换码并发兑换只有一次会成功
+    pub fn consume(
+        &self,
+        client: &str,
+        post_id: &str,
+        redeemcode: &str,
+        now: u64,
+    ) -> Result<IssuedRedeemCode, ApiError> {
+        self.update(client, post_id, |issued| {
+            if issued.redeemcode != redeemcode {
+                return Err(ApiError::NotFound(format!(
+                    "Redeem code was not issued for post {}",
+                    post_id
+                )));
+            }
+            if issued.revoked_at.is_some() {
+                return Err(ApiError::RedeemCodeRevoked(format!(
+                    "Redeem code for post {} has been revoked",
+                    post_id
+                )));
+            }
+            if let Some(consumed_at) = issued.consumed_at {
+                return Err(ApiError::RedeemCodeConsumed(format!(
+                    "Redeem code for post {} was consumed at {}",
+                    post_id, consumed_at
+                )));
+            }
+            issued.consumed_at = Some(now);
+            Ok(())
+        })
+    }
+
+    /// 作废某个推文的兑换码; 重复作废保留第一次的作废时间
+    pub fn revoke(
+        &self,
+        client: &str,
+        post_id: &str,
+        now: u64,
+    ) -> Result<IssuedRedeemCode, ApiError> {
+        self.update(client, post_id, |issued| {
+            issued.revoked_at.get_or_insert(now);
+            Ok(())
+        })
+    }
+
+    /// 在一个写事务中读出、修改并写回一条记录; `update` 报错时事务不提交
+    fn update(
+        &self,
+        client: &str,
+        post_id: &str,
+        update: impl FnOnce(&mut IssuedRedeemCode) -> Result<(), ApiError>,
+    ) -> Result<IssuedRedeemCode, ApiError> {
+        let key = store_key(client, post_id);
+        let txn = self.db.begin_write().map_err(store_error)?;
+
+        let issued = {
+            let mut table = txn.open_table(ISSUED_CODES).map_err(store_error)?;
+            let existing: Option<IssuedRedeemCode> = table
+                .get(key.as_str())
+                .map_err(store_error)?
+                .map(|existing| serde_json::from_slice(existing.value()))
+                .transpose()
+                .map_err(store_error)?;
+            let mut issued = existing.ok_or_else(|| {
+                ApiError::NotFound(format!(
+                    "No redeem code has been issued for post {}",
+                    post_id
+                ))
+            })?;
+
+            update(&mut issued)?;
+            let value = serde_json::to_vec(&issued).map_err(store_error)?;
+            table
+                .insert(key.as_str(), value.as_slice())
+                .map_err(store_error)?;
+            issued
+        };
+        txn.commit().map_err(store_error)?;
+
+        Ok(issued)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn issued(post_id: &str, redeemcode: &str) -> IssuedRedeemCode {
+        IssuedRedeemCode {
+            client: "twitter".to_string(),
+            post_id: post_id.to_string(),
+            redeemcode: redeemcode.to_string(),
+            signature: "signature".to_string(),
+            policy_id: "legacy".to_string(),
+            key_id: "00000000".to_string(),
+            issued_at: 1_736_932_298,
+            revoked_at: None,
+            consumed_at: None,
+        }
+    }
+
+    #[test]
+    fn test_return_existing() {
+        let store = RedeemStore::in_memory(ReplayPolicy::ReturnExisting).unwrap();
+        assert_eq!(store.get("twitter", "42").unwrap(), None);
+
+        let (first, new) = store.issue(issued("42", "first")).unwrap();
+        assert_eq!(first.redeemcode, "first");
+        assert!(new);
+
+        // 重复提交拿到的是第一次签发的兑换码
+        let (second, new) = store.issue(issued("42", "second")).unwrap();
+        assert_eq!(second, first);
+        assert!(!new);
+        assert_eq!(store.get("twitter", "42").unwrap(), Some(first));
+        assert_eq!(store.len().unwrap(), 1);
+
+        // 不同的推文互不影响
+        assert_eq!(
+            store.issue(issued("43", "other")).unwrap().0.redeemcode,
+            "other"
+        );
+        assert_eq!(store.len().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_reject() {
+        let store = RedeemStore::in_memory(ReplayPolicy::Reject).unwrap();
+
+        store.issue(issued("42", "first")).unwrap();
+        assert!(matches!(
+            store.issue(issued("42", "second")),
+            Err(ApiError::AlreadyRedeemed(_))
+        ));
+        assert_eq!(
+            store.get("twitter", "42").unwrap().unwrap().redeemcode,
+            "first"
+        );
+    }
+
+    #[test]
+    fn test_consume() {
+        let store = RedeemStore::in_memory(ReplayPolicy::ReturnExisting).unwrap();
+        assert!(matches!(
+            store.consume("twitter", "42", "first", 1_736_932_300),
+            Err(ApiError::NotFound(_))
+        ));
+
+        store.issue(issued("42", "first")).unwrap();
+        // 兑换码必须与签发记录一致
+        assert!(matches!(
+            store.consume("twitter", "42", "forged", 1_736_932_300),
+            Err(ApiError::NotFound(_))
+        ));
+
+        let consumed = store
+            .consume("twitter", "42", "first", 1_736_932_300)
+            .unwrap();
+        assert_eq!(consumed.consumed_at, Some(1_736_932_300));
+        assert_eq!(store.get("twitter", "42").unwrap(), Some(consumed));
+
+        // 同一个兑换码只能兑换一次
+        assert!(matches!(
+            store.consume("twitter", "42", "first", 1_736_932_301),
+            Err(ApiError::RedeemCodeConsumed(_))
+        ));
+        assert_eq!(
+            store.get("twitter", "42").unwrap().unwrap().consumed_at,
+            Some(1_736_932_300)
+        );
+    }
+
+    #[test]
+    fn test_revoke() {
+        let store = RedeemStore::in_memory(ReplayPolicy::ReturnExisting).unwrap();
+        assert!(matches!(
+            store.revoke("twitter", "42", 1_736_932_300),
+            Err(ApiError::NotFound(_))
+        ));
+
+        store.issue(issued("42", "first")).unwrap();
+        let revoked = store.revoke("twitter", "42", 1_736_932_300).unwrap();
+        assert_eq!(revoked.revoked_at, Some(1_736_932_300));
+        // 重复作废保留第一次的时间
+        assert_eq!(
+            store
+                .revoke("twitter", "42", 1_736_932_301)
+                .unwrap()
+                .revoked_at,
+            Some(1_736_932_300)
+        );
+
+        // 作废后不能兑换, 兑换失败不会写入 consumed_at
+        assert!(matches!(
+            store.consume("twitter", "42", "first", 1_736_932_302),
+            Err(ApiError::RedeemCodeRevoked(_))
+        ));
+        assert_eq!(
+            store.get("twitter", "42").unwrap().unwrap().consumed_at,
+            None
+        );
+    }
+
+    #[test]
+    fn test_check_writable() {
+        let store = RedeemStore::in_memory(ReplayPolicy::ReturnExisting).unwrap();
+        store.check_writable(1_736_932_298).unwrap();
+        store.check_writable(1_736_932_299).unwrap();
+        // 检查记录不计入兑换码
+        assert_eq!(store.len().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_replay_policy_from_str() {
+        assert_eq!(
+            "return_existing".parse::<ReplayPolicy>().unwrap(),
+            ReplayPolicy::ReturnExisting
+        );
+        assert_eq!(
+            "reject".parse::<ReplayPolicy>().unwrap(),
+            ReplayPolicy::Reject
+        );
+        assert!("ignore".parse::<ReplayPolicy>().is_err());
+    }
+}
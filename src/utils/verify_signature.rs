@@ -1,13 +1,11 @@
 use hex;
-use p256::{
-    ecdsa::{signature::Verifier, Signature, VerifyingKey},
-    pkcs8::{DecodePrivateKey, DecodePublicKey},
-};
-use std::str;
+use p256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
 
 use crate::error::ApiError;
+use crate::utils::notary_trust_store::NotaryTrustStore;
 
 pub fn verify_signature(
+    verifying_key: &VerifyingKey,
     attribute_hex: &str,
     attribute_name: &str,
     signature: &str,
@@ -20,11 +18,55 @@ pub fn verify_signature(
         ));
     }
 
+    //message
+    let message = hex::decode(attribute_hex).map_err(|_| {
+        ApiError::SignatureError("Invalid message format: must be a valid hex string".to_string())
+    })?;
+
+    verify_with_key(verifying_key, &message, signature)
+}
+
+/// 校验 notary 对整个 session 的签名, 签名覆盖的是 application_data 解码后的字节
+///
+/// 返回验证通过的公钥, 同一个 session 的 attribute 也必须由该公钥签名
+pub fn verify_session_signature(
+    verifying_keys: &[VerifyingKey],
+    application_data: &str,
+    signature: &str,
+) -> Result<VerifyingKey, ApiError> {
+    let application_data = application_data.replace(char::is_whitespace, "");
+    let message = hex::decode(application_data).map_err(|_| {
+        ApiError::SessionSignatureError(
+            "Invalid application data format: must be a valid hex string".to_string(),
+        )
+    })?;
+
+    for verifying_key in verifying_keys {
+        let is_valid =
+            verify_with_key(verifying_key, &message, signature).map_err(|err| match err {
+                ApiError::SignatureError(msg) => ApiError::SessionSignatureError(msg),
+                other => other,
+            })?;
+
+        if is_valid {
+            return Ok(*verifying_key);
+        }
+    }
+
+    Err(ApiError::SessionSignatureError(
+        "Session signature does not cover the application data".to_string(),
+    ))
+}
+
+fn verify_with_key(
+    verifying_key: &VerifyingKey,
+    message: &[u8],
+    signature: &str,
+) -> Result<bool, ApiError> {
     //signature
     let signature_bytes = hex::decode(signature).map_err(|_| {
         ApiError::SignatureError("Invalid signature format: must be a valid hex string".to_string())
     })?;
-    // println!("signature_bytes: {:?}", signature_bytes);
 
     let signature = Signature::from_slice(&signature_bytes).map_err(|_| {
         ApiError::SignatureError(
@@ -32,39 +74,65 @@ pub fn verify_signature(
         )
     })?;
 
-    //message
-    let application_data = hex::decode(attribute_hex).map_err(|_| {
-        ApiError::SignatureError("Invalid message format: must be a valid hex string".to_string())
-    })?;
-
-    //当前的写法是从私钥获取verifying key , 因为esper提供的公钥私钥不匹配
-    let verifying_key = VerifyingKey::from(notary_private_key());
-
-    let result = verifying_key.verify(&application_data, &signature).is_ok();
-    // println!("verification result: {}", result);
-
-    Ok(result)
+    Ok(verifying_key.verify(message, &signature).is_ok())
 }
 
-/// 返回保存的公钥
-fn _notary_pubkey() -> p256::PublicKey {
-    let pem_file = str::from_utf8(include_bytes!("../notary/notary.pub")).unwrap();
-    p256::PublicKey::from_public_key_pem(pem_file).unwrap()
-}
+/// 自检使用的已知向量: 信任列表中每个公钥都要提供 notary 对它的签名
+const SELF_CHECK_ATTRIBUTE_NAME: &str = "bookmark_count: 0";
+
+/// 启动时自检: 当前有效的每个 notary 公钥都必须能验证自己的已知向量,
+/// 一个公钥配置正确不能掩盖另一个公钥的错误
+pub fn notary_self_check(trust_store: &NotaryTrustStore, now: u64) -> Result<(), ApiError> {
+    let attribute_hex = hex::encode(SELF_CHECK_ATTRIBUTE_NAME);
+    let mut checked = 0;
+
+    for notary in trust_store.notaries() {
+        for key in notary.keys.iter().filter(|key| key.is_valid_at(now)) {
+            let signature = key.self_check_signature.as_deref().ok_or_else(|| {
+                ApiError::SignatureError(format!(
+                    "Notary {} has a key without a self-check signature",
+                    notary.id
+                ))
+            })?;
+            if !verify_signature(
+                &key.verifying_key,
+                &attribute_hex,
+                SELF_CHECK_ATTRIBUTE_NAME,
+                signature,
+            )? {
+                return Err(ApiError::SignatureError(format!(
+                    "Notary {} has a key that cannot verify its self-check vector",
+                    notary.id
+                )));
+            }
+            checked += 1;
+        }
+    }
 
-fn notary_private_key() -> p256::ecdsa::SigningKey {
-    let pem_file = str::from_utf8(include_bytes!("../notary/notary.key")).unwrap();
-    let private_key = p256::ecdsa::SigningKey::from_pkcs8_pem(pem_file).unwrap();
-    private_key
+    if checked == 0 {
+        return Err(ApiError::SignatureError(
+            "Trust store has no notary key valid at this time".to_string(),
+        ));
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
 
     use p256::ecdsa::{signature::SignerMut, SigningKey};
+    use p256::pkcs8::EncodePublicKey;
     use rand_core::OsRng;
 
     use super::*;
+    use crate::utils::notary_trust_store::now_unix;
+
+    fn notary_pubkey() -> VerifyingKey {
+        NotaryTrustStore::example()
+            .unwrap()
+            .resolve("https://notary.example.com", now_unix())
+            .unwrap()[0]
+    }
 
     #[test]
     fn test_verifying_key() {
@@ -85,21 +153,57 @@ mod tests {
     }
 
     #[test]
-    fn test_esper_private_key_and_pubkey() {
-        // let private_key = notary_private_key();
-        // let public_key = _notary_pubkey();
-
-        // let verifying_key_from_private = VerifyingKey::from(&private_key);
-        // let verifying_key_from_pubkey = VerifyingKey::from(&public_key);
-
-        // //esper提供的公钥和私钥不是一对
-        // assert_eq!(verifying_key_from_private, verifying_key_from_pubkey);
+    fn test_notary_self_check() {
+        let now = now_unix();
+        assert!(notary_self_check(&NotaryTrustStore::example().unwrap(), now).is_ok());
+
+        let mut good_key = SigningKey::random(&mut OsRng);
+        let good_signature: Signature = good_key.sign(SELF_CHECK_ATTRIBUTE_NAME.as_bytes());
+        let broken_key = SigningKey::random(&mut OsRng);
+        let key = |signing_key: &SigningKey, signature: Option<String>, not_after: Option<u64>| {
+            serde_json::json!({
+                "public_key_pem": VerifyingKey::from(signing_key).to_public_key_pem(Default::default()).unwrap(),
+                "not_before": null,
+                "not_after": not_after,
+                "self_check_signature": signature,
+            })
+        };
+        let trust_store = |keys: Vec<serde_json::Value>| {
+            let json = serde_json::json!({
+                "notaries": [{ "id": "other", "urls": ["https://other.example.com"], "keys": keys }]
+            });
+            NotaryTrustStore::from_json(&json.to_string()).unwrap()
+        };
+        let good = || {
+            key(
+                &good_key,
+                Some(hex::encode(good_signature.to_bytes())),
+                None,
+            )
+        };
+
+        assert!(notary_self_check(&trust_store(vec![good()]), now).is_ok());
+
+        // 一个公钥正确不能掩盖另一个公钥的错误
+        let broken = key(
+            &broken_key,
+            Some(hex::encode(good_signature.to_bytes())),
+            None,
+        );
+        assert!(notary_self_check(&trust_store(vec![good(), broken]), now).is_err());
+        let unsigned = key(&broken_key, None, None);
+        assert!(notary_self_check(&trust_store(vec![good(), unsigned]), now).is_err());
+
+        // 已过期的公钥不再检查, 但至少要有一个有效的公钥
+        let expired = key(&broken_key, None, Some(1));
+        assert!(notary_self_check(&trust_store(vec![good(), expired.clone()]), now).is_ok());
+        assert!(notary_self_check(&trust_store(vec![expired]), now).is_err());
     }
 
     #[test]
     fn test_sign_p256() {
         // Generate a random private key
-        let mut signing_key = notary_private_key();
+        let mut signing_key = SigningKey::random(&mut OsRng);
 
         // Message to be signed
         let message = b"bookmark_count";
@@ -107,39 +211,44 @@ mod tests {
         // Sign the message
         let signature: Signature = signing_key.sign(message);
 
-        // Verify the signature (optional, for demonstration)
-
         let verifying_key = VerifyingKey::from(&signing_key);
-
-        println!(
-            "verifying_key from private key: {:?}",
-            verifying_key.to_sec1_bytes()
-        );
-
         assert!(verifying_key.verify(message, &signature).is_ok());
-        println!("test");
 
         let message_hex = hex::encode(message);
-        let result = verify_signature(&message_hex, "bookmark_count", &signature.to_string());
+        let result = verify_signature(
+            &verifying_key,
+            &message_hex,
+            "bookmark_count",
+            &signature.to_string(),
+        );
         assert!(result.unwrap());
+
+        // notary 公钥不能验证其他私钥的签名
+        let result = verify_signature(
+            &notary_pubkey(),
+            &message_hex,
+            "bookmark_count",
+            &signature.to_string(),
+        );
+        assert!(!result.unwrap());
     }
+
     #[test]
     fn test_verify_signature() {
-        // let attribute_hex = "626f6f6b6d61726b5f636f756e743a2030";
-        // let signature = "8bea50d146c6597c44e1a6292d74cc78653b25390d1be8fd352a915db7a01c428f0e68d8d1f5e27e018df019cd5814e1fdfcc959265d6d82e7ea45ff7ca1a32b";
+        let verifying_key = notary_pubkey();
 
-        // let attribute_hex_2 = "636f6e74656e743a2022446f626279207468696e6b732063613a36703678674879463741654536545a6b536d46736b6f34343477716f503135696355537169326a664769504e2073686f756c6420616c77617973206361727279206120736f636b20696e20746865697220706f636b6574e28094796f75206e65766572206b6e6f77207768656e2066726565646f6d206d6967687420636f6d65206b6e6f636b696e6721205c6e5c6e446f626279206f6e6365207573656420612074656163757020746f20736f6c76652061206269672070726f626c656d2c2070726f76696e67206576656e2074686520736d616c6c657374207468696e67732063616e20686f6c6420677265617420706f7765722e22";
-        // let signature_2 ="fc4461697608e8ef0a01e0ebc953b91dddf987f27323ddcb2a687225590bac194e55b7d18969d5a81c3e43d0f8e90862a91e97068798e5d2c8c15c6a38a8c186";
-        // let content = "content: \"Dobby thinks ca:6p6xgHyF7AeE6TZkSmFsko444wqoP15icUSqi2jfGiPN should always carry a sock in their pocket—you never know when freedom might come knocking! \\n\\nDobby once used a teacup to solve a big problem, proving even the smallest things can hold great power.\"";
-        // println!("Testing with:");
-        // println!("attribute_hex: {}", attribute_hex);
-        // println!("signature: {}", signature);
+        let attribute_name = "author: \"1248668065148973061\"";
+        let signature = "98e045ba2ddb0cc9cb6a98b1714032823e92b4ae4f7b59cf80058eba0250e9841edefcb508311af74984c58f2efd5bd7d121242364be0f38e2a668f5d5439fa5";
+        let attribute_hex = hex::encode(attribute_name);
 
-        // // let result = verify_signature(attribute_hex, "bookmark_count", signature);
-        // // assert!(result.unwrap());
+        let result = verify_signature(&verifying_key, &attribute_hex, attribute_name, signature);
+        assert!(result.unwrap());
 
-        // let result_2 = verify_signature(attribute_hex_2, "content", signature_2);
-        // assert!(result_2.unwrap());
+        // 篡改后的属性不能通过验证
+        let tampered_name = "author: \"1248668065148973062\"";
+        let tampered_hex = hex::encode(tampered_name);
+        let result = verify_signature(&verifying_key, &tampered_hex, tampered_name, signature);
+        assert!(!result.unwrap());
     }
 
     #[test]
@@ -147,24 +256,59 @@ mod tests {
         // 模拟服务器接收到的 attribute_name
         let attribute_name =
             "content:\"Dobby thinks ca:6p6xgHyF7AeE6TZkSmFsko444wqoP15icUSqi2jfGiPN is\"";
-        println!("Original attribute_name: {}", attribute_name);
 
         // 生成 hex
         let attribute_hex = hex::encode(attribute_name);
-        println!("Generated attribute_hex: {}", attribute_hex);
 
         // 生成签名
-        let mut signing_key = notary_private_key();
+        let mut signing_key = SigningKey::random(&mut OsRng);
+        let verifying_key = VerifyingKey::from(&signing_key);
 
         let signature: Signature = signing_key.sign(attribute_name.as_bytes());
         let signature_hex = hex::encode(signature.to_bytes());
-        println!("Generated signature: {}", signature_hex);
 
         // 验证签名
-        let result = verify_signature(&attribute_hex, attribute_name, &signature_hex);
-        println!("Verification result: {:?}", result);
+        let result = verify_signature(
+            &verifying_key,
+            &attribute_hex,
+            attribute_name,
+            &signature_hex,
+        );
 
         // 添加断言确保测试通过
-        assert!(result.is_ok());
+        assert!(result.unwrap());
+    }
+
+    #[test]
+    fn test_verify_session_signature() {
+        let application_data = b"GET /i/api/graphql/TweetDetail HTTP/1.1\r\n\r\n";
+        let application_data_hex = hex::encode(application_data);
+
+        let mut signing_key = SigningKey::random(&mut OsRng);
+        let verifying_key = VerifyingKey::from(&signing_key);
+        let signature: Signature = signing_key.sign(application_data);
+        let signature_hex = hex::encode(signature.to_bytes());
+
+        let other_key = VerifyingKey::from(&SigningKey::random(&mut OsRng));
+        let matched = verify_session_signature(
+            &[other_key, verifying_key],
+            &application_data_hex,
+            &signature_hex,
+        )
+        .unwrap();
+        assert_eq!(matched, verifying_key);
+
+        // 篡改 application_data 后签名应当失效
+        let tampered_hex = hex::encode(b"GET /i/api/graphql/UserByRestId HTTP/1.1\r\n\r\n");
+        assert!(matches!(
+            verify_session_signature(&[verifying_key], &tampered_hex, &signature_hex),
+            Err(ApiError::SessionSignatureError(_))
+        ));
+
+        // 非法的签名格式
+        assert!(matches!(
+            verify_session_signature(&[verifying_key], &application_data_hex, "not-hex"),
+            Err(ApiError::SessionSignatureError(_))
+        ));
     }
 }